@@ -0,0 +1,504 @@
+//! reading and writing compiled scripts as `.loxc` files, so that a script can
+//! be compiled once and run later without scanning or parsing it again.
+//!
+//! a file is the magic bytes [MAGIC], a little-endian `u32` format version,
+//! and then the top-level function. a function is written as
+//!
+//! - its name: a `u8` flag followed by a string if the flag is 1
+//! - its arity and upvalue count as `u32`s
+//! - its code: a `u32` length followed by the bytes
+//! - its line table and then its column table: each a `u32` count of runs
+//!   followed by two varints per run, the number of bytes since the previous
+//!   run started and the line or column
+//! - its constants: a `u32` count followed by a tag byte and payload for each
+//!
+//! strings are a `u32` byte length followed by UTF-8, and numbers are the
+//! little-endian bits of the `f64`. varints are unsigned LEB128 of at most 32
+//! bits. functions nested in the constant table are
+//! written recursively in the same format
+
+use std::fmt::Display;
+
+use crate::{
+    chunk::{write_varint, Chunk, Columns, LineRun},
+    compile::MAX_NESTING,
+    value::{ObjFunction, ObjKind, ObjRef, Value},
+    vm::{InterpretError, Vm},
+};
+
+/// the first bytes of every `.loxc` file
+pub const MAGIC: &[u8; 4] = b"loxc";
+
+/// bumped whenever the layout or the opcode numbering changes, since old
+/// files can't be run by a vm with a different instruction set
+pub const VERSION: u32 = 3;
+
+const TAG_NIL: u8 = 0;
+const TAG_FALSE: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_NUMBER: u8 = 3;
+const TAG_STRING: u8 = 4;
+const TAG_FUNCTION: u8 = 5;
+
+/// the reasons a `.loxc` file can fail to load
+#[derive(Debug, PartialEq)]
+pub enum LoadError {
+    /// the file doesn't start with [MAGIC]
+    NotBytecode,
+    /// the file was written for a different format version
+    Version(u32),
+    /// the file ended in the middle of a function
+    Truncated,
+    /// the file is well-formed up to some part that makes no sense
+    Invalid(&'static str),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::NotBytecode => write!(f, "Not a compiled Lox file."),
+            LoadError::Version(v) => write!(
+                f,
+                "Unsupported bytecode version {v}, expected version \
+                 {VERSION}. Recompile the script."
+            ),
+            LoadError::Truncated => write!(f, "Unexpected end of bytecode."),
+            LoadError::Invalid(msg) => write!(f, "Invalid bytecode: {msg}."),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// returns true if `bytes` look like a compiled script rather than source
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+impl Vm {
+    /// compile `source` and return it as the contents of a `.loxc` file.
+    /// compile errors are reported just like in [Vm::interpret]
+    pub fn compile_to_bytes(
+        &mut self,
+        source: String,
+    ) -> Result<Vec<u8>, InterpretError> {
+        let function = self.compile(source)?;
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        write_u32(&mut out, VERSION);
+        write_function(&mut out, function.as_function().unwrap());
+        Ok(out)
+    }
+
+    /// load a script compiled by [Vm::compile_to_bytes] and run it
+    pub fn interpret_bytes(
+        &mut self,
+        bytes: &[u8],
+    ) -> Result<(), InterpretError> {
+        let function = match self.load(bytes) {
+            Ok(function) => function,
+            Err(e) => {
+                eprintln!("{e}");
+                return Err(InterpretError::CompileError);
+            }
+        };
+        let function = self.allocate_object(ObjKind::Function(function));
+        self.run_script(function)
+    }
+
+    fn load(&mut self, bytes: &[u8]) -> Result<ObjFunction, LoadError> {
+        let mut reader = Reader {
+            bytes,
+            pos: 0,
+            depth: 0,
+        };
+        if reader.bytes(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(LoadError::NotBytecode);
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(LoadError::Version(version));
+        }
+        let function = self.load_function(&mut reader)?;
+        if reader.pos != bytes.len() {
+            return Err(LoadError::Invalid("trailing bytes after script"));
+        }
+        Ok(function)
+    }
+
+    /// read a function and everything it refers to. the function is built in
+    /// a fresh [Compiler](crate::compile::Compiler) so that the objects
+    /// loaded into its constant table are rooted the same way they are while
+    /// compiling from source
+    fn load_function(
+        &mut self,
+        reader: &mut Reader,
+    ) -> Result<ObjFunction, LoadError> {
+        // loading recurses for each nested function, so a crafted file could
+        // otherwise overflow the stack
+        if reader.depth == MAX_NESTING {
+            return Err(LoadError::Invalid("functions nested too deeply"));
+        }
+        let enclosing = std::mem::take(&mut self.compiler);
+        self.compiler.enclosing = Some(Box::new(enclosing));
+
+        reader.depth += 1;
+        let result = self.load_function_into_compiler(reader);
+        reader.depth -= 1;
+
+        let compiler = std::mem::take(&mut self.compiler);
+        self.compiler = *compiler.enclosing.unwrap();
+        result.map(|()| compiler.function)
+    }
+
+    fn load_function_into_compiler(
+        &mut self,
+        reader: &mut Reader,
+    ) -> Result<(), LoadError> {
+        self.compiler.function.name = match reader.u8()? {
+            0 => None,
+            1 => Some(self.load_string(reader)?),
+            _ => return Err(LoadError::Invalid("bad function name flag")),
+        };
+        self.compiler.function.arity = reader.u32()? as usize;
+        self.compiler.function.upvalue_count = reader.u32()? as usize;
+
+        let len = reader.u32()? as usize;
+        let code = reader.bytes(len)?.to_vec();
+
+        let lines = reader.runs(code.len(), "bad line table")?;
+        let columns = reader.runs(code.len(), "bad column table")?;
+
+        let chunk = &mut self.compiler.function.chunk;
+        chunk.code = code;
+        chunk.lines = lines
+            .into_iter()
+            .map(|(start, line)| LineRun {
+                start: start as u32,
+                line: line as u32,
+            })
+            .collect();
+        chunk.columns = Columns::default();
+        for (start, column) in columns {
+            chunk.columns.write(start, column);
+        }
+
+        let count = reader.u32()?;
+        for _ in 0..count {
+            let value = self.load_value(reader)?;
+            self.compiler.function.chunk.constants.push(value);
+        }
+        Ok(())
+    }
+
+    fn load_value(&mut self, reader: &mut Reader) -> Result<Value, LoadError> {
+        Ok(match reader.u8()? {
+            TAG_NIL => Value::nil(),
+            TAG_FALSE => Value::boolean(false),
+            TAG_TRUE => Value::boolean(true),
+            TAG_NUMBER => {
+                // a NaN's payload could pass for another kind of value when
+                // NaN boxing, so only the canonical one is let through
+                let n = f64::from_bits(reader.u64()?);
+                Value::number(if n.is_nan() { f64::NAN } else { n })
+            }
+            TAG_STRING => Value::obj(self.load_string(reader)?),
+            TAG_FUNCTION => {
+                let function = self.load_function(reader)?;
+                Value::obj(self.allocate_object(ObjKind::Function(function)))
+            }
+            _ => return Err(LoadError::Invalid("unknown constant type")),
+        })
+    }
+
+    fn load_string(
+        &mut self,
+        reader: &mut Reader,
+    ) -> Result<ObjRef, LoadError> {
+        let len = reader.u32()? as usize;
+        let chars = std::str::from_utf8(reader.bytes(len)?)
+            .map_err(|_| LoadError::Invalid("string is not UTF-8"))?;
+        Ok(self.copy_string(chars))
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, n: u32) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn write_string(out: &mut Vec<u8>, s: ObjRef) {
+    let chars = &s.as_string().unwrap().chars;
+    write_u32(out, chars.len() as u32);
+    out.extend_from_slice(chars.as_bytes());
+}
+
+fn write_function(out: &mut Vec<u8>, function: &ObjFunction) {
+    match function.name {
+        Some(name) => {
+            out.push(1);
+            write_string(out, name);
+        }
+        None => out.push(0),
+    }
+    write_u32(out, function.arity as u32);
+    write_u32(out, function.upvalue_count as u32);
+    write_chunk(out, &function.chunk);
+}
+
+fn write_chunk(out: &mut Vec<u8>, chunk: &Chunk) {
+    write_u32(out, chunk.code.len() as u32);
+    out.extend_from_slice(&chunk.code);
+
+    let lines: Vec<_> = chunk
+        .lines
+        .iter()
+        .map(|run| (run.start as usize, run.line as usize))
+        .collect();
+    write_runs(out, &lines);
+    write_runs(out, &chunk.columns.runs().collect::<Vec<_>>());
+
+    write_u32(out, chunk.constants.len() as u32);
+    for i in 0..chunk.constants.len() {
+        write_value(out, chunk.constants[i]);
+    }
+}
+
+/// write a line or column table given each run's start and value
+fn write_runs(out: &mut Vec<u8>, runs: &[(usize, usize)]) {
+    write_u32(out, runs.len() as u32);
+    let mut previous = 0;
+    for &(start, value) in runs {
+        write_varint(out, start - previous);
+        write_varint(out, value);
+        previous = start;
+    }
+}
+
+fn write_value(out: &mut Vec<u8>, value: Value) {
+    if value.is_nil() {
+        out.push(TAG_NIL);
+    } else if let Some(b) = value.as_bool() {
+        out.push(if b { TAG_TRUE } else { TAG_FALSE });
+    } else if let Some(n) = value.as_number() {
+        out.push(TAG_NUMBER);
+        out.extend_from_slice(&n.to_bits().to_le_bytes());
+    } else {
+        let obj = value.as_obj().unwrap();
+        match &obj.kind {
+            ObjKind::String(_) => {
+                out.push(TAG_STRING);
+                write_string(out, obj);
+            }
+            ObjKind::Function(function) => {
+                out.push(TAG_FUNCTION);
+                write_function(out, function);
+            }
+            // the compiler only ever puts strings and functions in the
+            // constant table
+            _ => unreachable!("{} in constant table", obj.type_name()),
+        }
+    }
+}
+
+/// a cursor over the bytes of a `.loxc` file
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// the number of functions currently being loaded
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], LoadError> {
+        let end = self.pos.checked_add(len).ok_or(LoadError::Truncated)?;
+        let bytes =
+            self.bytes.get(self.pos..end).ok_or(LoadError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, LoadError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, LoadError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn varint(&mut self) -> Result<u32, LoadError> {
+        let mut n = 0u64;
+        for shift in (0..32).step_by(7) {
+            let byte = self.u8()?;
+            n |= ((byte & 0x7f) as u64) << shift;
+            if byte < 0x80 {
+                return u32::try_from(n)
+                    .map_err(|_| LoadError::Invalid("varint too large"));
+            }
+        }
+        Err(LoadError::Invalid("varint too large"))
+    }
+
+    /// read a line or column table for `len` bytes of code as each run's
+    /// start and value. the runs have to start at zero and go forward
+    /// without leaving the code
+    fn runs(
+        &mut self,
+        len: usize,
+        error: &'static str,
+    ) -> Result<Vec<(usize, usize)>, LoadError> {
+        let count = self.u32()?;
+        let mut runs = Vec::new();
+        let mut start = 0;
+        for i in 0..count {
+            // only the first run starts at zero
+            let delta = self.varint()? as usize;
+            start += delta;
+            if (delta == 0) != (i == 0) || start >= len {
+                return Err(LoadError::Invalid(error));
+            }
+            runs.push((start, self.varint()? as usize));
+        }
+        if len > 0 && runs.is_empty() {
+            return Err(LoadError::Invalid(error));
+        }
+        Ok(runs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "
+        var greeting = \"hi\";
+        fun outer(a, b) {
+            fun inner() { return a + 1.5; }
+            return inner;
+        }
+        print outer(1, 2)();
+    ";
+
+    /// compare two functions by their contents, recursing into nested ones
+    fn assert_same(a: &ObjFunction, b: &ObjFunction) {
+        assert_eq!(a.to_string(), b.to_string());
+        assert_eq!(a.arity, b.arity);
+        assert_eq!(a.upvalue_count, b.upvalue_count);
+        assert_eq!(a.chunk.code, b.chunk.code);
+        assert_eq!(a.chunk.lines, b.chunk.lines);
+        assert_eq!(a.chunk.columns, b.chunk.columns);
+        assert_eq!(a.chunk.constants.len(), b.chunk.constants.len());
+        for i in 0..a.chunk.constants.len() {
+            let (x, y) = (a.chunk.constants[i], b.chunk.constants[i]);
+            match (x.as_function(), y.as_function()) {
+                (Some(x), Some(y)) => assert_same(x, y),
+                _ => assert_eq!(x, y),
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut vm = Vm::new();
+        let bytes = vm.compile_to_bytes(SOURCE.to_owned()).unwrap();
+        assert!(is_bytecode(&bytes));
+
+        let want = vm.compile(SOURCE.to_owned()).unwrap();
+        // keep the expected function alive while loading allocates
+        vm.push(Value::obj(want));
+        let got = vm.load(&bytes).unwrap();
+        assert_same(&got, want.as_function().unwrap());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut vm = Vm::new();
+        let mut bytes = vm.compile_to_bytes(SOURCE.to_owned()).unwrap();
+        bytes[MAGIC.len()..MAGIC.len() + 4]
+            .copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(
+            vm.load(&bytes).err(),
+            Some(LoadError::Version(VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        let mut vm = Vm::new();
+        assert_eq!(vm.load(b"print 1;").err(), Some(LoadError::NotBytecode));
+
+        let bytes = vm.compile_to_bytes(SOURCE.to_owned()).unwrap();
+        for len in [MAGIC.len() + 2, bytes.len() / 2, bytes.len() - 1] {
+            assert_eq!(
+                vm.load(&bytes[..len]).err(),
+                Some(LoadError::Truncated)
+            );
+        }
+
+        // a chain of functions each holding the next as its only constant.
+        // every function's header is the same: no name, arity, upvalues,
+        // code, line runs, or column runs, and then one constant
+        let mut nested = MAGIC.to_vec();
+        write_u32(&mut nested, VERSION);
+        for _ in 0..100_000 {
+            nested.push(0);
+            for n in [0, 0, 0, 0, 0, 1] {
+                write_u32(&mut nested, n);
+            }
+            nested.push(TAG_FUNCTION);
+        }
+        assert_eq!(
+            vm.load(&nested).err(),
+            Some(LoadError::Invalid("functions nested too deeply"))
+        );
+
+        // the compiler stops at the same depth, so it never writes a file
+        // that can't be loaded, and it doesn't recurse any further into
+        // functions nested past it
+        for depth in [MAX_NESTING, 100_000] {
+            let source = "fun f() {".repeat(depth) + &"}".repeat(depth);
+            assert!(vm.compile(source).is_err());
+        }
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(vm.load(&trailing), Err(LoadError::Invalid(_))));
+
+        // the line table follows the script's code. its first run has to
+        // start at zero, and a varint can't run on past 32 bits
+        let bytes = vm.compile_to_bytes("print 1;".to_owned()).unwrap();
+        let code = MAGIC.len() + 4 + 1 + 4 + 4;
+        let lines = code + 4 + bytes[code] as usize;
+        let mut bad = bytes.clone();
+        bad[lines + 4] = 1;
+        assert_eq!(
+            vm.load(&bad).err(),
+            Some(LoadError::Invalid("bad line table"))
+        );
+        let mut bad = bytes.clone();
+        bad.splice(lines + 4..lines + 5, [0x80; 5]);
+        assert_eq!(
+            vm.load(&bad).err(),
+            Some(LoadError::Invalid("varint too large"))
+        );
+    }
+
+    #[test]
+    fn canonicalizes_nan() {
+        let mut vm = Vm::new();
+        let mut bytes = vm.compile_to_bytes("print 1.5;".to_owned()).unwrap();
+        // as a NaN-boxed value, these bits would be a pointer to an object
+        let at = bytes
+            .windows(8)
+            .position(|w| w == 1.5f64.to_le_bytes())
+            .unwrap();
+        bytes[at..at + 8].copy_from_slice(&0xfffc000000001000u64.to_le_bytes());
+        let function = vm.load(&bytes).unwrap();
+        let constant = function.chunk.constants[0];
+        assert!(constant.as_obj().is_none());
+        assert!(constant.as_number().unwrap().is_nan());
+    }
+}
@@ -1,11 +1,34 @@
 use crate::value::{Value, ValueArray};
 
+/// each instruction that takes a constant index has a `Long` variant right
+/// after it, which is the same except that the index is a three-byte,
+/// big-endian operand. the compiler only uses those in chunks with more than
+/// 256 constants
 #[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum OpCode {
     Constant,
+    ConstantLong,
     Nil,
     True,
     False,
+    Pop,
+    GetLocal,
+    SetLocal,
+    GetGlobal,
+    GetGlobalLong,
+    DefineGlobal,
+    DefineGlobalLong,
+    SetGlobal,
+    SetGlobalLong,
+    GetUpvalue,
+    SetUpvalue,
+    GetProperty,
+    GetPropertyLong,
+    SetProperty,
+    SetPropertyLong,
+    GetSuper,
+    GetSuperLong,
     Equal,
     Greater,
     Less,
@@ -15,7 +38,24 @@ pub enum OpCode {
     Divide,
     Not,
     Negate,
+    Print,
+    Jump,
+    JumpIfFalse,
+    Loop,
+    Call,
+    Invoke,
+    InvokeLong,
+    SuperInvoke,
+    SuperInvokeLong,
+    Closure,
+    ClosureLong,
+    CloseUpvalue,
     Return,
+    Class,
+    ClassLong,
+    Inherit,
+    Method,
+    MethodLong,
 }
 
 impl From<OpCode> for u8 {
@@ -31,9 +71,27 @@ impl TryInto<OpCode> for u8 {
         use OpCode::*;
         match self {
             x if x == Constant as u8 => Ok(Constant),
+            x if x == ConstantLong as u8 => Ok(ConstantLong),
             x if x == Nil as u8 => Ok(Nil),
             x if x == True as u8 => Ok(True),
             x if x == False as u8 => Ok(False),
+            x if x == Pop as u8 => Ok(Pop),
+            x if x == GetLocal as u8 => Ok(GetLocal),
+            x if x == SetLocal as u8 => Ok(SetLocal),
+            x if x == GetGlobal as u8 => Ok(GetGlobal),
+            x if x == GetGlobalLong as u8 => Ok(GetGlobalLong),
+            x if x == DefineGlobal as u8 => Ok(DefineGlobal),
+            x if x == DefineGlobalLong as u8 => Ok(DefineGlobalLong),
+            x if x == SetGlobal as u8 => Ok(SetGlobal),
+            x if x == SetGlobalLong as u8 => Ok(SetGlobalLong),
+            x if x == GetUpvalue as u8 => Ok(GetUpvalue),
+            x if x == SetUpvalue as u8 => Ok(SetUpvalue),
+            x if x == GetProperty as u8 => Ok(GetProperty),
+            x if x == GetPropertyLong as u8 => Ok(GetPropertyLong),
+            x if x == SetProperty as u8 => Ok(SetProperty),
+            x if x == SetPropertyLong as u8 => Ok(SetPropertyLong),
+            x if x == GetSuper as u8 => Ok(GetSuper),
+            x if x == GetSuperLong as u8 => Ok(GetSuperLong),
             x if x == Equal as u8 => Ok(Equal),
             x if x == Greater as u8 => Ok(Greater),
             x if x == Less as u8 => Ok(Less),
@@ -43,16 +101,157 @@ impl TryInto<OpCode> for u8 {
             x if x == Divide as u8 => Ok(Divide),
             x if x == Not as u8 => Ok(Not),
             x if x == Negate as u8 => Ok(Negate),
+            x if x == Print as u8 => Ok(Print),
+            x if x == Jump as u8 => Ok(Jump),
+            x if x == JumpIfFalse as u8 => Ok(JumpIfFalse),
+            x if x == Loop as u8 => Ok(Loop),
+            x if x == Call as u8 => Ok(Call),
+            x if x == Invoke as u8 => Ok(Invoke),
+            x if x == InvokeLong as u8 => Ok(InvokeLong),
+            x if x == SuperInvoke as u8 => Ok(SuperInvoke),
+            x if x == SuperInvokeLong as u8 => Ok(SuperInvokeLong),
+            x if x == Closure as u8 => Ok(Closure),
+            x if x == ClosureLong as u8 => Ok(ClosureLong),
+            x if x == CloseUpvalue as u8 => Ok(CloseUpvalue),
             x if x == Return as u8 => Ok(Return),
+            x if x == Class as u8 => Ok(Class),
+            x if x == ClassLong as u8 => Ok(ClassLong),
+            x if x == Inherit as u8 => Ok(Inherit),
+            x if x == Method as u8 => Ok(Method),
+            x if x == MethodLong as u8 => Ok(MethodLong),
             _ => Err(()),
         }
     }
 }
 
+impl OpCode {
+    /// the variant of `self` with a three-byte constant index
+    ///
+    /// # Panics
+    ///
+    /// if `self` has no constant operand
+    pub(crate) fn long(self) -> Self {
+        use OpCode::*;
+        match self {
+            Constant => ConstantLong,
+            GetGlobal => GetGlobalLong,
+            DefineGlobal => DefineGlobalLong,
+            SetGlobal => SetGlobalLong,
+            GetProperty => GetPropertyLong,
+            SetProperty => SetPropertyLong,
+            GetSuper => GetSuperLong,
+            Invoke => InvokeLong,
+            SuperInvoke => SuperInvokeLong,
+            Closure => ClosureLong,
+            Class => ClassLong,
+            Method => MethodLong,
+            _ => panic!("{self:?} has no constant operand"),
+        }
+    }
+
+    /// whether `self` is the `Long` variant of an instruction
+    pub(crate) fn is_long(self) -> bool {
+        use OpCode::*;
+        matches!(
+            self,
+            ConstantLong
+                | GetGlobalLong
+                | DefineGlobalLong
+                | SetGlobalLong
+                | GetPropertyLong
+                | SetPropertyLong
+                | GetSuperLong
+                | InvokeLong
+                | SuperInvokeLong
+                | ClosureLong
+                | ClassLong
+                | MethodLong
+        )
+    }
+}
+
+/// the source line of a run of consecutive bytes in a chunk. the compiler
+/// emits many bytes for each line, so storing a line per run instead of per
+/// byte keeps the line table much smaller than the code
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct LineRun {
+    /// offset of the first byte in the run
+    pub(crate) start: u32,
+    pub(crate) line: u32,
+}
+
+/// the source column of each byte in a chunk. columns change with nearly every
+/// token, so instead of a [LineRun]-style struct per run they're packed as
+/// pairs of varints: the number of bytes since the previous run started, then
+/// the column. that's usually two bytes per token. the packed form can only be
+/// read from the start, which is fine since columns are only looked up to
+/// report errors
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Columns {
+    pub(crate) packed: Vec<u8>,
+    /// the start and column of the last run
+    last: Option<(usize, usize)>,
+}
+
+impl Columns {
+    /// record that the byte at `offset` is in `column`, starting a new run if
+    /// the column changed. offsets must be written in increasing order
+    pub(crate) fn write(&mut self, offset: usize, column: usize) {
+        if self.last.is_some_and(|(_, last)| last == column) {
+            return;
+        }
+        let previous = self.last.map_or(0, |(start, _)| start);
+        write_varint(&mut self.packed, offset - previous);
+        write_varint(&mut self.packed, column);
+        self.last = Some((offset, column));
+    }
+
+    /// each run's starting offset and column, in order
+    pub(crate) fn runs(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let mut pos = 0;
+        let mut start = 0;
+        std::iter::from_fn(move || {
+            if pos == self.packed.len() {
+                return None;
+            }
+            start += read_varint(&self.packed, &mut pos);
+            Some((start, read_varint(&self.packed, &mut pos)))
+        })
+    }
+}
+
+/// append `n` as an unsigned LEB128 varint: seven bits at a time, least
+/// significant first, with the high bit set on every byte but the last
+pub(crate) fn write_varint(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+/// read a varint written by [write_varint] starting at `*pos`, and advance
+/// `pos` past it
+fn read_varint(bytes: &[u8], pos: &mut usize) -> usize {
+    let mut n = 0;
+    let mut shift = 0;
+    loop {
+        let byte = bytes[*pos];
+        *pos += 1;
+        n |= ((byte & 0x7f) as usize) << shift;
+        if byte < 0x80 {
+            return n;
+        }
+        shift += 7;
+    }
+}
+
 pub struct Chunk {
     pub(crate) code: Vec<u8>,
     pub(crate) constants: ValueArray,
-    pub(crate) lines: Vec<usize>,
+    /// sorted by `start`, with the first run starting at zero
+    pub(crate) lines: Vec<LineRun>,
+    pub(crate) columns: Columns,
 }
 
 impl Chunk {
@@ -61,17 +260,50 @@ impl Chunk {
             code: Vec::new(),
             constants: ValueArray::new(),
             lines: Vec::new(),
+            columns: Columns::default(),
         }
     }
 
-    pub fn write_chunk(&mut self, byte: impl Into<u8>, line: usize) {
+    pub fn write_chunk(
+        &mut self,
+        byte: impl Into<u8>,
+        line: usize,
+        column: usize,
+    ) {
+        let offset = self.code.len();
+        if self.lines.last().is_none_or(|run| run.line != line as u32) {
+            self.lines.push(LineRun {
+                start: offset as u32,
+                line: line as u32,
+            });
+        }
+        self.columns.write(offset, column);
         self.code.push(byte.into());
-        self.lines.push(line);
     }
 
-    pub fn add_constant(&mut self, value: Value) -> u8 {
+    /// the source line of the byte at `offset`
+    pub fn get_line(&self, offset: usize) -> usize {
+        let index = self
+            .lines
+            .partition_point(|run| run.start as usize <= offset);
+        self.lines[index - 1].line as usize
+    }
+
+    /// the source column of the byte at `offset`
+    pub fn get_column(&self, offset: usize) -> usize {
+        self.columns
+            .runs()
+            .take_while(|&(start, _)| start <= offset)
+            .last()
+            .unwrap()
+            .1
+    }
+
+    /// add `value` to the constant table and return its index. it's up to the
+    /// caller to check that the index fits in the operand it's used with
+    pub fn add_constant(&mut self, value: Value) -> usize {
         self.constants.push(value);
-        self.constants.len() as u8 - 1
+        self.constants.len() - 1
     }
 }
 
@@ -80,3 +312,39 @@ impl Default for Chunk {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_runs() {
+        let mut chunk = Chunk::new();
+        let positions =
+            [(1, 1), (1, 1), (1, 5), (2, 5), (2, 5), (2, 300), (4, 1)];
+        for (line, column) in positions {
+            chunk.write_chunk(OpCode::Nil, line, column);
+        }
+
+        assert_eq!(chunk.lines.len(), 3);
+        let runs: Vec<_> = chunk.columns.runs().collect();
+        assert_eq!(runs, [(0, 1), (2, 5), (5, 300), (6, 1)]);
+        // column 300 takes two bytes
+        assert_eq!(chunk.columns.packed.len(), 9);
+        for (offset, (line, column)) in positions.into_iter().enumerate() {
+            assert_eq!(chunk.get_line(offset), line);
+            assert_eq!(chunk.get_column(offset), column);
+        }
+    }
+
+    #[test]
+    fn varints() {
+        for n in [0, 1, 127, 128, 300, 16383, 16384, u32::MAX as usize] {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, n);
+            let mut pos = 0;
+            assert_eq!(read_varint(&bytes, &mut pos), n);
+            assert_eq!(pos, bytes.len());
+        }
+    }
+}
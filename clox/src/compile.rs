@@ -1,7 +1,9 @@
 use crate::{
     chunk::{Chunk, OpCode},
     scanner::{Scanner, Token, TokenType},
-    value::Value,
+    table::Table,
+    value::{ObjFunction, ObjKind, ObjRef, Value},
+    verify::{verify_function, VerifyError, VerifyErrorKind},
     vm::{InterpretError, Vm},
     DEBUG_PRINT_CODE,
 };
@@ -14,6 +16,163 @@ pub(crate) struct Parser {
     panic_mode: bool,
 }
 
+/// corresponds to UINT8_COUNT, the number of distinct values a one-byte
+/// operand can address
+const UINT8_COUNT: usize = u8::MAX as usize + 1;
+
+/// the most functions that can be nested inside each other, counting the
+/// top-level script. loading a `.loxc` file enforces the same limit
+pub(crate) const MAX_NESTING: usize = 256;
+
+/// the largest constant index that fits in the operand of a `Long`
+/// instruction
+const MAX_LONG_CONSTANT: usize = (1 << 24) - 1;
+
+struct Local {
+    name: Token,
+    /// the scope depth of the block that declared this local, or `None` while
+    /// its initializer is still being compiled
+    depth: Option<usize>,
+    /// whether any closure captures this local, in which case it has to be
+    /// moved to the heap when it goes out of scope
+    is_captured: bool,
+}
+
+/// a variable captured by the function being compiled
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Upvalue {
+    /// the slot of the local in the enclosing function if `is_local`, or else
+    /// the index of the enclosing function's own upvalue
+    index: u8,
+    is_local: bool,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+enum FunctionType {
+    Function,
+    Initializer,
+    Method,
+    #[default]
+    Script,
+}
+
+/// the state for compiling a single function. the C version keeps a global
+/// pointer to the current compiler, here it lives on the [Vm] next to the
+/// [Parser], and the compilers for enclosing functions hang off of it
+#[derive(Default)]
+pub(crate) struct Compiler {
+    pub(crate) enclosing: Option<Box<Compiler>>,
+    /// the function being built. it only becomes a heap object once it's
+    /// finished, in [Vm::end_compiler]
+    pub(crate) function: ObjFunction,
+    typ: FunctionType,
+    locals: Vec<Local>,
+    upvalues: Vec<Upvalue>,
+    scope_depth: usize,
+    /// the constant index of each name already added by
+    /// [Vm::identifier_constant], so that a name used many times only takes
+    /// up one slot in the constant table
+    identifiers: Table,
+}
+
+impl Compiler {
+    fn new(typ: FunctionType, name: Option<ObjRef>) -> Self {
+        Self {
+            enclosing: None,
+            function: ObjFunction {
+                name,
+                ..Default::default()
+            },
+            typ,
+            // the vm uses slot zero for the function being called, or for the
+            // receiver in methods. outside of methods, giving the local an
+            // empty name means user code can never refer to it
+            locals: vec![Local {
+                name: match typ {
+                    FunctionType::Initializer | FunctionType::Method => {
+                        Token::new(TokenType::This, "this".to_owned(), 0, 0)
+                    }
+                    FunctionType::Function | FunctionType::Script => {
+                        Token::default()
+                    }
+                },
+                depth: Some(0),
+                is_captured: false,
+            }],
+            upvalues: Vec::new(),
+            scope_depth: 0,
+            identifiers: Table::new(),
+        }
+    }
+
+    /// return the stack slot of the local variable `name`, or `None` if it
+    /// isn't a local in this function. the `Err` case is a message to report
+    fn resolve_local(&self, name: &Token) -> Result<Option<u8>, &'static str> {
+        let Some((slot, local)) = self
+            .locals
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, local)| local.name.lexeme == name.lexeme)
+        else {
+            return Ok(None);
+        };
+        if local.depth.is_none() {
+            return Err("Can't read local variable in its own initializer.");
+        }
+        Ok(Some(slot as u8))
+    }
+
+    /// return the index of the upvalue capturing `name` from an enclosing
+    /// function, adding upvalues along the chain of compilers as needed, or
+    /// `None` if `name` is a global
+    fn resolve_upvalue(
+        &mut self,
+        name: &Token,
+    ) -> Result<Option<u8>, &'static str> {
+        let Some(enclosing) = self.enclosing.as_mut() else {
+            return Ok(None);
+        };
+
+        if let Some(local) = enclosing.resolve_local(name)? {
+            enclosing.locals[local as usize].is_captured = true;
+            return self.add_upvalue(local, true).map(Some);
+        }
+
+        if let Some(upvalue) = enclosing.resolve_upvalue(name)? {
+            return self.add_upvalue(upvalue, false).map(Some);
+        }
+
+        Ok(None)
+    }
+
+    fn add_upvalue(
+        &mut self,
+        index: u8,
+        is_local: bool,
+    ) -> Result<u8, &'static str> {
+        let upvalue = Upvalue { index, is_local };
+        if let Some(i) = self.upvalues.iter().position(|&u| u == upvalue) {
+            return Ok(i as u8);
+        }
+
+        if self.upvalues.len() == UINT8_COUNT {
+            return Err("Too many closure variables in function.");
+        }
+
+        self.upvalues.push(upvalue);
+        self.function.upvalue_count += 1;
+        Ok(self.upvalues.len() as u8 - 1)
+    }
+}
+
+/// tracks the class declaration being compiled, if any, so that `this` and
+/// `super` can be rejected outside of methods
+pub(crate) struct ClassCompiler {
+    enclosing: Option<Box<ClassCompiler>>,
+    has_superclass: bool,
+}
+
 #[repr(u8)]
 #[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
 enum Precedence {
@@ -52,7 +211,7 @@ impl From<u8> for Precedence {
 }
 
 // this doesn't feel like it's going to work but it might
-type ParseFn = for<'a, 'b> fn(&'a mut Vm, &'b mut Scanner);
+type ParseFn = for<'a, 'b> fn(&'a mut Vm, &'b mut Scanner, bool);
 
 #[derive(Default, Clone)]
 struct ParseRule {
@@ -75,29 +234,36 @@ fn get_rule(typ: TokenType) -> &'static ParseRule {
     &RULES[typ as u8 as usize]
 }
 
+/// a token for a name that doesn't appear in the source, like the hidden
+/// `super` local
+fn synthetic_token(text: &str) -> Token {
+    Token::new(TokenType::Identifier, text.to_owned(), 0, 0)
+}
+
 impl Vm {
     pub(crate) fn compile(
         &mut self,
         source: String,
-    ) -> Result<Chunk, InterpretError> {
-        let chunk = Chunk::new();
+    ) -> Result<ObjRef, InterpretError> {
         let mut scanner = Scanner::new(source);
 
-        self.chunk = Some(chunk);
+        self.compiler = Compiler::new(FunctionType::Script, None);
+        self.class_compiler = None;
 
         self.parser.had_error = false;
         self.parser.panic_mode = false;
 
         self.advance(&mut scanner);
-        self.expression(&mut scanner);
-        self.consume(TokenType::Eof, "Expect end of expression.", &mut scanner);
+        while !self.matches(TokenType::Eof, &mut scanner) {
+            self.declaration(&mut scanner);
+        }
 
-        self.end();
+        let (function, _) = self.end_compiler();
 
         if self.parser.had_error {
             Err(InterpretError::CompileError)
         } else {
-            Ok(std::mem::take(self.chunk.as_mut().unwrap()))
+            Ok(self.allocate_object(ObjKind::Function(function)))
         }
     }
 
@@ -110,7 +276,8 @@ impl Vm {
                 break;
             }
 
-            self.error_at_current(&scanner.get_token(&self.parser.current));
+            let message = self.parser.current.lexeme.clone();
+            self.error_at_current(&message);
         }
     }
 
@@ -128,9 +295,43 @@ impl Vm {
         self.error_at_current(message);
     }
 
+    fn check(&self, typ: TokenType) -> bool {
+        self.parser.current.typ == typ
+    }
+
+    fn matches(&mut self, typ: TokenType, scanner: &mut Scanner) -> bool {
+        if !self.check(typ) {
+            return false;
+        }
+        self.advance(scanner);
+        true
+    }
+
     fn emit_byte(&mut self, byte: impl Into<u8>) {
-        let line = self.parser.previous.line;
-        self.current_chunk().write_chunk(byte, line);
+        let Token { line, column, .. } = self.parser.previous;
+        self.current_chunk().write_chunk(byte, line, column);
+    }
+
+    /// emit a backwards jump to `loop_start`
+    fn emit_loop(&mut self, loop_start: usize) {
+        self.emit_byte(OpCode::Loop);
+
+        // + 2 to also jump over the operand of Loop itself
+        let offset = self.current_chunk().code.len() - loop_start + 2;
+        if offset > u16::MAX as usize {
+            self.error("Loop body too large.");
+        }
+
+        let [hi, lo] = (offset as u16).to_be_bytes();
+        self.emit_bytes(hi, lo);
+    }
+
+    /// emit `instruction` with a placeholder operand, returning the offset of
+    /// the operand for [Vm::patch_jump] to fill in later
+    fn emit_jump(&mut self, instruction: OpCode) -> usize {
+        self.emit_byte(instruction);
+        self.emit_bytes(0xff, 0xff);
+        self.current_chunk().code.len() - 2
     }
 
     fn emit_bytes<T: Into<u8>>(&mut self, byte1: T, byte2: T) {
@@ -138,14 +339,66 @@ impl Vm {
         self.emit_byte(byte2);
     }
 
-    pub(crate) fn end(&mut self) {
+    /// start compiling a new function nested in the current one. returns
+    /// false, without starting one, if functions are already nested as deeply
+    /// as they can be
+    fn init_compiler(&mut self, typ: FunctionType) -> bool {
+        let depth = std::iter::successors(Some(&self.compiler), |c| {
+            c.enclosing.as_deref()
+        })
+        .count();
+        if depth == MAX_NESTING {
+            self.error("Too many nested functions.");
+            return false;
+        }
+        let name = self.parser.previous.lexeme.clone();
+        let name = self.take_string(name);
+        let compiler = Compiler::new(typ, Some(name));
+        let enclosing = std::mem::replace(&mut self.compiler, compiler);
+        self.compiler.enclosing = Some(Box::new(enclosing));
+        true
+    }
+
+    /// finish the current function and return it along with the variables it
+    /// captures, making its enclosing compiler current again
+    fn end_compiler(&mut self) -> (ObjFunction, Vec<Upvalue>) {
         self.emit_return();
+        let function = std::mem::take(&mut self.compiler.function);
+        let upvalues = std::mem::take(&mut self.compiler.upvalues);
+        self.check_stack_depth(&function);
         if DEBUG_PRINT_CODE && !self.parser.had_error {
-            self.current_chunk().disassemble("code");
+            let name = match function.name {
+                Some(name) => name.to_string(),
+                None => "<script>".to_owned(),
+            };
+            function.chunk.disassemble(&name);
+        }
+        if let Some(enclosing) = self.compiler.enclosing.take() {
+            self.compiler = *enclosing;
+        }
+        (function, upvalues)
+    }
+
+    /// report a finished function that could need more stack than the vm
+    /// makes room for in a call. slots aren't counted as code is emitted, so
+    /// this runs the verifier's analysis over the whole function, which would
+    /// otherwise reject it as invalid bytecode when it's run
+    fn check_stack_depth(&mut self, function: &ObjFunction) {
+        if self.parser.had_error {
+            return;
+        }
+        if let Err(VerifyError {
+            kind: VerifyErrorKind::StackTooDeep,
+            line,
+            ..
+        }) = verify_function(function)
+        {
+            eprintln!("[line {line}] Error: Expression too deeply nested.");
+            self.parser.had_error = true;
         }
     }
 
-    fn binary(&mut self, scanner: &mut Scanner) {
+    fn binary(&mut self, scanner: &mut Scanner, _can_assign: bool) {
         let operator_type = self.parser.previous.typ;
         let rule = get_rule(operator_type);
         self.parse_precedence(
@@ -172,7 +425,54 @@ impl Vm {
         }
     }
 
-    fn literal(&mut self, scanner: &mut Scanner) {
+    fn call(&mut self, scanner: &mut Scanner, _can_assign: bool) {
+        let arg_count = self.argument_list(scanner);
+        self.emit_bytes(OpCode::Call as u8, arg_count);
+    }
+
+    fn dot(&mut self, scanner: &mut Scanner, can_assign: bool) {
+        self.consume(
+            TokenType::Identifier,
+            "Expect property name after '.'.",
+            scanner,
+        );
+        let name = self.parser.previous.clone();
+        let name = self.identifier_constant(&name);
+
+        if can_assign && self.matches(TokenType::Equal, scanner) {
+            self.expression(scanner);
+            self.emit_indexed(OpCode::SetProperty, name);
+        } else if self.matches(TokenType::LeftParen, scanner) {
+            // a method call, which doesn't need a bound method
+            let arg_count = self.argument_list(scanner);
+            self.emit_indexed(OpCode::Invoke, name);
+            self.emit_byte(arg_count);
+        } else {
+            self.emit_indexed(OpCode::GetProperty, name);
+        }
+    }
+
+    fn and(&mut self, scanner: &mut Scanner, _can_assign: bool) {
+        let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+
+        self.emit_byte(OpCode::Pop);
+        self.parse_precedence(Precedence::And, scanner);
+
+        self.patch_jump(end_jump);
+    }
+
+    fn or(&mut self, scanner: &mut Scanner, _can_assign: bool) {
+        let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+        let end_jump = self.emit_jump(OpCode::Jump);
+
+        self.patch_jump(else_jump);
+        self.emit_byte(OpCode::Pop);
+
+        self.parse_precedence(Precedence::Or, scanner);
+        self.patch_jump(end_jump);
+    }
+
+    fn literal(&mut self, scanner: &mut Scanner, _can_assign: bool) {
         match self.parser.previous.typ {
             TokenType::False => self.emit_byte(OpCode::False),
             TokenType::True => self.emit_byte(OpCode::True),
@@ -181,7 +481,7 @@ impl Vm {
         }
     }
 
-    fn grouping(&mut self, scanner: &mut Scanner) {
+    fn grouping(&mut self, scanner: &mut Scanner, _can_assign: bool) {
         self.expression(scanner);
         self.consume(
             TokenType::RightParen,
@@ -190,15 +490,90 @@ impl Vm {
         );
     }
 
-    fn number(&mut self, scanner: &mut Scanner) {
-        let value = scanner
-            .get_token(&self.parser.previous)
-            .parse::<f64>()
-            .unwrap();
+    fn number(&mut self, scanner: &mut Scanner, _can_assign: bool) {
+        let value = self.parser.previous.lexeme.parse::<f64>().unwrap();
         self.emit_constant(Value::number(value));
     }
 
-    fn unary(&mut self, scanner: &mut Scanner) {
+    fn string(&mut self, scanner: &mut Scanner, _can_assign: bool) {
+        let lexeme = &self.parser.previous.lexeme;
+        // trim the leading and trailing quotes
+        let chars = lexeme[1..lexeme.len() - 1].to_owned();
+        let s = self.take_string(chars);
+        self.emit_constant(Value::obj(s));
+    }
+
+    fn variable(&mut self, scanner: &mut Scanner, can_assign: bool) {
+        let name = self.parser.previous.clone();
+        self.named_variable(&name, scanner, can_assign);
+    }
+
+    fn named_variable(
+        &mut self,
+        name: &Token,
+        scanner: &mut Scanner,
+        can_assign: bool,
+    ) {
+        let (get_op, set_op, arg) = if let Some(arg) = self.resolve_local(name)
+        {
+            (OpCode::GetLocal, OpCode::SetLocal, arg as usize)
+        } else if let Some(arg) = self.resolve_upvalue(name) {
+            (OpCode::GetUpvalue, OpCode::SetUpvalue, arg as usize)
+        } else {
+            let arg = self.identifier_constant(name);
+            (OpCode::GetGlobal, OpCode::SetGlobal, arg)
+        };
+
+        if can_assign && self.matches(TokenType::Equal, scanner) {
+            self.expression(scanner);
+            self.emit_indexed(set_op, arg);
+        } else {
+            self.emit_indexed(get_op, arg);
+        }
+    }
+
+    fn this(&mut self, scanner: &mut Scanner, _can_assign: bool) {
+        if self.class_compiler.is_none() {
+            self.error("Can't use 'this' outside of a class.");
+            return;
+        }
+        // `this` is just a local variable in slot zero
+        self.variable(scanner, false);
+    }
+
+    fn super_(&mut self, scanner: &mut Scanner, _can_assign: bool) {
+        match &self.class_compiler {
+            None => self.error("Can't use 'super' outside of a class."),
+            Some(class) if !class.has_superclass => {
+                self.error("Can't use 'super' in a class with no superclass.")
+            }
+            Some(_) => {}
+        }
+
+        self.consume(TokenType::Dot, "Expect '.' after 'super'.", scanner);
+        self.consume(
+            TokenType::Identifier,
+            "Expect superclass method name.",
+            scanner,
+        );
+        let name = self.parser.previous.clone();
+        let name = self.identifier_constant(&name);
+
+        // the receiver and the superclass both have to be on the stack for
+        // the method lookup and the call
+        self.named_variable(&synthetic_token("this"), scanner, false);
+        if self.matches(TokenType::LeftParen, scanner) {
+            let arg_count = self.argument_list(scanner);
+            self.named_variable(&synthetic_token("super"), scanner, false);
+            self.emit_indexed(OpCode::SuperInvoke, name);
+            self.emit_byte(arg_count);
+        } else {
+            self.named_variable(&synthetic_token("super"), scanner, false);
+            self.emit_indexed(OpCode::GetSuper, name);
+        }
+    }
+
+    fn unary(&mut self, scanner: &mut Scanner, _can_assign: bool) {
         let operator_type = self.parser.previous.typ;
 
         // compile the operand
@@ -219,41 +594,622 @@ impl Vm {
         self.advance(scanner);
         let prefix_rule = get_rule(self.parser.previous.typ).prefix;
         let Some(rule) = prefix_rule else {
-	    self.error("Expect expression.");
-	    return;
-	};
+            self.error("Expect expression.");
+            return;
+        };
 
-        rule(self, scanner);
+        let can_assign = precedence <= Precedence::Assignment;
+        rule(self, scanner, can_assign);
 
         while precedence <= get_rule(self.parser.current.typ).precedence {
             self.advance(scanner);
-            (get_rule(self.parser.previous.typ).infix.unwrap())(self, scanner);
+            let infix_rule = get_rule(self.parser.previous.typ).infix.unwrap();
+            infix_rule(self, scanner, can_assign);
+        }
+
+        if can_assign && self.matches(TokenType::Equal, scanner) {
+            self.error("Invalid assignment target.");
         }
     }
 
+    /// add the name of the variable `name` to the constant table as a string,
+    /// returning its index. names are only added the first time they're seen
+    fn identifier_constant(&mut self, name: &Token) -> usize {
+        let s = self.copy_string(&name.lexeme);
+        if let Some(index) = self.compiler.identifiers.get(s) {
+            return index.as_number().unwrap() as usize;
+        }
+        let index = self.make_constant(Value::obj(s));
+        self.compiler
+            .identifiers
+            .set(s, Value::number(index as f64));
+        index
+    }
+
+    /// return the stack slot of the local variable `name`, or `None` if it
+    /// isn't a local and should be treated as a global
+    fn resolve_local(&mut self, name: &Token) -> Option<u8> {
+        self.compiler.resolve_local(name).unwrap_or_else(|message| {
+            self.error(message);
+            None
+        })
+    }
+
+    fn resolve_upvalue(&mut self, name: &Token) -> Option<u8> {
+        self.compiler
+            .resolve_upvalue(name)
+            .unwrap_or_else(|message| {
+                self.error(message);
+                None
+            })
+    }
+
+    fn add_local(&mut self, name: Token) {
+        if self.compiler.locals.len() == UINT8_COUNT {
+            self.error("Too many local variables in function.");
+            return;
+        }
+        self.compiler.locals.push(Local {
+            name,
+            depth: None,
+            is_captured: false,
+        });
+    }
+
+    /// record the existence of a local variable. globals are late bound, so
+    /// this does nothing at the top level
+    fn declare_variable(&mut self) {
+        if self.compiler.scope_depth == 0 {
+            return;
+        }
+
+        let name = self.parser.previous.clone();
+        let scope_depth = self.compiler.scope_depth;
+        let duplicate = self
+            .compiler
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth.is_none_or(|d| d >= scope_depth))
+            .any(|local| local.name.lexeme == name.lexeme);
+        if duplicate {
+            self.error("Already a variable with this name in this scope.");
+        }
+
+        self.add_local(name);
+    }
+
+    fn parse_variable(
+        &mut self,
+        message: &str,
+        scanner: &mut Scanner,
+    ) -> usize {
+        self.consume(TokenType::Identifier, message, scanner);
+
+        self.declare_variable();
+        if self.compiler.scope_depth > 0 {
+            return 0;
+        }
+
+        let name = self.parser.previous.clone();
+        self.identifier_constant(&name)
+    }
+
+    fn mark_initialized(&mut self) {
+        // a function declared at the top level is a global, not a local
+        if self.compiler.scope_depth == 0 {
+            return;
+        }
+        let depth = self.compiler.scope_depth;
+        self.compiler.locals.last_mut().unwrap().depth = Some(depth);
+    }
+
+    fn define_variable(&mut self, global: usize) {
+        // locals are already in place on the stack
+        if self.compiler.scope_depth > 0 {
+            self.mark_initialized();
+            return;
+        }
+        self.emit_indexed(OpCode::DefineGlobal, global);
+    }
+
+    fn argument_list(&mut self, scanner: &mut Scanner) -> u8 {
+        let mut arg_count = 0;
+        if !self.check(TokenType::RightParen) {
+            loop {
+                self.expression(scanner);
+                if arg_count == 255 {
+                    self.error("Can't have more than 255 arguments.");
+                }
+                arg_count += 1;
+                if !self.matches(TokenType::Comma, scanner) {
+                    break;
+                }
+            }
+        }
+        self.consume(
+            TokenType::RightParen,
+            "Expect ')' after arguments.",
+            scanner,
+        );
+        arg_count as u8
+    }
+
+    /// emit an instruction loading `value`
     pub(crate) fn emit_constant(&mut self, value: Value) {
-        let c = self.make_constant(value);
-        self.emit_bytes(OpCode::Constant as u8, c);
+        let constant = self.make_constant(value);
+        self.emit_indexed(OpCode::Constant, constant);
     }
 
-    pub(crate) fn make_constant(&mut self, value: Value) -> u8 {
-        // apparently it's impossible to construct a constant that is too large
-        // and we can't check it
-        self.current_chunk().add_constant(value)
+    /// emit `op` with the operand `index`, switching to the `Long` variant of
+    /// `op` once the index outgrows a single byte. local and upvalue slots
+    /// always fit, so only constant indexes ever take the long form
+    fn emit_indexed(&mut self, op: OpCode, index: usize) {
+        if let Ok(index) = u8::try_from(index) {
+            self.emit_bytes(op as u8, index);
+        } else {
+            let [_, hi, mid, lo] = (index as u32).to_be_bytes();
+            self.emit_byte(op.long());
+            self.emit_bytes(hi, mid);
+            self.emit_byte(lo);
+        }
     }
 
+    /// add `value` to the constant table and return its index
+    pub(crate) fn make_constant(&mut self, value: Value) -> usize {
+        let constant = self.current_chunk().add_constant(value);
+        if constant > MAX_LONG_CONSTANT {
+            self.error("Too many constants in one chunk.");
+            return 0;
+        }
+        constant
+    }
+
+    /// emit an implicit return, which returns `this` from initializers and
+    /// nil from everything else
     pub(crate) fn emit_return(&mut self) {
-        self.emit_byte(OpCode::Return as u8);
+        if self.compiler.typ == FunctionType::Initializer {
+            self.emit_bytes(OpCode::GetLocal as u8, 0);
+        } else {
+            self.emit_byte(OpCode::Nil);
+        }
+        self.emit_byte(OpCode::Return);
+    }
+
+    /// fill in the operand of the jump at `offset` to land on the next
+    /// instruction to be emitted
+    fn patch_jump(&mut self, offset: usize) {
+        // -2 to adjust for the jump offset itself
+        let jump = self.current_chunk().code.len() - offset - 2;
+
+        if jump > u16::MAX as usize {
+            self.error("Too much code to jump over.");
+        }
+
+        let [hi, lo] = (jump as u16).to_be_bytes();
+        self.current_chunk().code[offset] = hi;
+        self.current_chunk().code[offset + 1] = lo;
     }
 
     fn current_chunk(&mut self) -> &mut Chunk {
-        self.chunk.as_mut().unwrap()
+        &mut self.compiler.function.chunk
     }
 
     pub(crate) fn expression(&mut self, scanner: &mut Scanner) {
         self.parse_precedence(Precedence::Assignment, scanner);
     }
 
+    fn block(&mut self, scanner: &mut Scanner) {
+        while !self.check(TokenType::RightBrace) && !self.check(TokenType::Eof)
+        {
+            self.declaration(scanner);
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after block.", scanner);
+    }
+
+    fn begin_scope(&mut self) {
+        self.compiler.scope_depth += 1;
+    }
+
+    /// discard the locals declared in the scope being closed, both in the
+    /// compiler and on the vm's stack
+    fn end_scope(&mut self) {
+        self.compiler.scope_depth -= 1;
+
+        while self
+            .compiler
+            .locals
+            .last()
+            .is_some_and(|local| local.depth > Some(self.compiler.scope_depth))
+        {
+            let local = self.compiler.locals.pop().unwrap();
+            if local.is_captured {
+                self.emit_byte(OpCode::CloseUpvalue);
+            } else {
+                self.emit_byte(OpCode::Pop);
+            }
+        }
+    }
+
+    /// compile the parameters and body of a function, leaving a closure over
+    /// the finished function on the stack
+    fn function(&mut self, typ: FunctionType, scanner: &mut Scanner) {
+        if !self.init_compiler(typ) {
+            self.skip_function(scanner);
+            return;
+        }
+        self.begin_scope();
+
+        self.consume(
+            TokenType::LeftParen,
+            "Expect '(' after function name.",
+            scanner,
+        );
+        if !self.check(TokenType::RightParen) {
+            loop {
+                self.compiler.function.arity += 1;
+                if self.compiler.function.arity > 255 {
+                    self.error_at_current(
+                        "Can't have more than 255 parameters.",
+                    );
+                }
+                let constant =
+                    self.parse_variable("Expect parameter name.", scanner);
+                self.define_variable(constant);
+                if !self.matches(TokenType::Comma, scanner) {
+                    break;
+                }
+            }
+        }
+        self.consume(
+            TokenType::RightParen,
+            "Expect ')' after parameters.",
+            scanner,
+        );
+        self.consume(
+            TokenType::LeftBrace,
+            "Expect '{' before function body.",
+            scanner,
+        );
+        self.block(scanner);
+
+        // no end_scope, the locals are discarded with the whole frame
+        let (function, upvalues) = self.end_compiler();
+        let function = self.allocate_object(ObjKind::Function(function));
+        let constant = self.make_constant(Value::obj(function));
+        self.emit_indexed(OpCode::Closure, constant);
+
+        for upvalue in upvalues {
+            self.emit_bytes(upvalue.is_local as u8, upvalue.index);
+        }
+    }
+
+    fn method(&mut self, scanner: &mut Scanner) {
+        self.consume(TokenType::Identifier, "Expect method name.", scanner);
+        let name = self.parser.previous.clone();
+        let constant = self.identifier_constant(&name);
+
+        let typ = if name.lexeme == "init" {
+            FunctionType::Initializer
+        } else {
+            FunctionType::Method
+        };
+        self.function(typ, scanner);
+        self.emit_indexed(OpCode::Method, constant);
+    }
+
+    fn class_declaration(&mut self, scanner: &mut Scanner) {
+        self.consume(TokenType::Identifier, "Expect class name.", scanner);
+        let class_name = self.parser.previous.clone();
+        let name_constant = self.identifier_constant(&class_name);
+        self.declare_variable();
+
+        self.emit_indexed(OpCode::Class, name_constant);
+        self.define_variable(name_constant);
+
+        let class_compiler = ClassCompiler {
+            enclosing: self.class_compiler.take(),
+            has_superclass: false,
+        };
+        self.class_compiler = Some(Box::new(class_compiler));
+
+        if self.matches(TokenType::Less, scanner) {
+            self.consume(
+                TokenType::Identifier,
+                "Expect superclass name.",
+                scanner,
+            );
+            self.variable(scanner, false);
+
+            if class_name.lexeme == self.parser.previous.lexeme {
+                self.error("A class can't inherit from itself.");
+            }
+
+            // store the superclass in a local named `super` in a new scope so
+            // that each class gets its own, captured by the methods that use
+            // it
+            self.begin_scope();
+            self.add_local(synthetic_token("super"));
+            self.define_variable(0);
+
+            self.named_variable(&class_name, scanner, false);
+            self.emit_byte(OpCode::Inherit);
+            self.class_compiler.as_mut().unwrap().has_superclass = true;
+        }
+
+        // load the class back onto the stack for the Method instructions
+        self.named_variable(&class_name, scanner, false);
+        self.consume(
+            TokenType::LeftBrace,
+            "Expect '{' before class body.",
+            scanner,
+        );
+        while !self.check(TokenType::RightBrace) && !self.check(TokenType::Eof)
+        {
+            self.method(scanner);
+        }
+        self.consume(
+            TokenType::RightBrace,
+            "Expect '}' after class body.",
+            scanner,
+        );
+        self.emit_byte(OpCode::Pop);
+
+        let class_compiler = self.class_compiler.take().unwrap();
+        if class_compiler.has_superclass {
+            self.end_scope();
+        }
+        self.class_compiler = class_compiler.enclosing;
+    }
+
+    fn fun_declaration(&mut self, scanner: &mut Scanner) {
+        let global = self.parse_variable("Expect function name.", scanner);
+        // a function can refer to itself in its body, so it's initialized
+        // before compiling the body
+        self.mark_initialized();
+        self.function(FunctionType::Function, scanner);
+        self.define_variable(global);
+    }
+
+    fn var_declaration(&mut self, scanner: &mut Scanner) {
+        let global = self.parse_variable("Expect variable name.", scanner);
+
+        if self.matches(TokenType::Equal, scanner) {
+            self.expression(scanner);
+        } else {
+            self.emit_byte(OpCode::Nil);
+        }
+        self.consume(
+            TokenType::Semicolon,
+            "Expect ';' after variable declaration.",
+            scanner,
+        );
+
+        self.define_variable(global);
+    }
+
+    fn expression_statement(&mut self, scanner: &mut Scanner) {
+        self.expression(scanner);
+        self.consume(
+            TokenType::Semicolon,
+            "Expect ';' after expression.",
+            scanner,
+        );
+        self.emit_byte(OpCode::Pop);
+    }
+
+    fn for_statement(&mut self, scanner: &mut Scanner) {
+        self.begin_scope();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.", scanner);
+        if self.matches(TokenType::Semicolon, scanner) {
+            // no initializer
+        } else if self.matches(TokenType::Var, scanner) {
+            self.var_declaration(scanner);
+        } else {
+            self.expression_statement(scanner);
+        }
+
+        let mut loop_start = self.current_chunk().code.len();
+        let mut exit_jump = None;
+        if !self.matches(TokenType::Semicolon, scanner) {
+            self.expression(scanner);
+            self.consume(
+                TokenType::Semicolon,
+                "Expect ';' after loop condition.",
+                scanner,
+            );
+
+            // jump out of the loop if the condition is false
+            exit_jump = Some(self.emit_jump(OpCode::JumpIfFalse));
+            self.emit_byte(OpCode::Pop);
+        }
+
+        if !self.matches(TokenType::RightParen, scanner) {
+            // the increment runs after the body, so jump over it now and have
+            // the body loop back to it
+            let body_jump = self.emit_jump(OpCode::Jump);
+            let increment_start = self.current_chunk().code.len();
+            self.expression(scanner);
+            self.emit_byte(OpCode::Pop);
+            self.consume(
+                TokenType::RightParen,
+                "Expect ')' after for clauses.",
+                scanner,
+            );
+
+            self.emit_loop(loop_start);
+            loop_start = increment_start;
+            self.patch_jump(body_jump);
+        }
+
+        self.statement(scanner);
+        self.emit_loop(loop_start);
+
+        if let Some(exit_jump) = exit_jump {
+            self.patch_jump(exit_jump);
+            self.emit_byte(OpCode::Pop);
+        }
+
+        self.end_scope();
+    }
+
+    fn if_statement(&mut self, scanner: &mut Scanner) {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.", scanner);
+        self.expression(scanner);
+        self.consume(
+            TokenType::RightParen,
+            "Expect ')' after condition.",
+            scanner,
+        );
+
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_byte(OpCode::Pop);
+        self.statement(scanner);
+
+        let else_jump = self.emit_jump(OpCode::Jump);
+
+        self.patch_jump(then_jump);
+        self.emit_byte(OpCode::Pop);
+
+        if self.matches(TokenType::Else, scanner) {
+            self.statement(scanner);
+        }
+        self.patch_jump(else_jump);
+    }
+
+    fn print_statement(&mut self, scanner: &mut Scanner) {
+        self.expression(scanner);
+        self.consume(TokenType::Semicolon, "Expect ';' after value.", scanner);
+        self.emit_byte(OpCode::Print);
+    }
+
+    fn return_statement(&mut self, scanner: &mut Scanner) {
+        if self.compiler.typ == FunctionType::Script {
+            self.error("Can't return from top-level code.");
+        }
+
+        if self.matches(TokenType::Semicolon, scanner) {
+            self.emit_return();
+        } else {
+            if self.compiler.typ == FunctionType::Initializer {
+                self.error("Can't return a value from an initializer.");
+            }
+
+            self.expression(scanner);
+            self.consume(
+                TokenType::Semicolon,
+                "Expect ';' after return value.",
+                scanner,
+            );
+            self.emit_byte(OpCode::Return);
+        }
+    }
+
+    fn while_statement(&mut self, scanner: &mut Scanner) {
+        let loop_start = self.current_chunk().code.len();
+        self.consume(
+            TokenType::LeftParen,
+            "Expect '(' after 'while'.",
+            scanner,
+        );
+        self.expression(scanner);
+        self.consume(
+            TokenType::RightParen,
+            "Expect ')' after condition.",
+            scanner,
+        );
+
+        let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_byte(OpCode::Pop);
+        self.statement(scanner);
+        self.emit_loop(loop_start);
+
+        self.patch_jump(exit_jump);
+        self.emit_byte(OpCode::Pop);
+    }
+
+    /// skip the parameters and body of a function that was nested too deeply
+    /// to compile, without recursing into anything nested inside it. this
+    /// leaves the parser just past the function, so there's nothing left to
+    /// [synchronize](Self::synchronize) over
+    fn skip_function(&mut self, scanner: &mut Scanner) {
+        let mut depth = 0;
+        while !self.check(TokenType::Eof) {
+            match self.parser.current.typ {
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace if depth == 1 => {
+                    self.advance(scanner);
+                    self.parser.panic_mode = false;
+                    return;
+                }
+                TokenType::RightBrace => depth -= 1,
+                _ => {}
+            }
+            self.advance(scanner);
+        }
+    }
+
+    /// skip tokens until something that looks like a statement boundary so
+    /// that one mistake doesn't cause a cascade of errors
+    fn synchronize(&mut self, scanner: &mut Scanner) {
+        self.parser.panic_mode = false;
+
+        while self.parser.current.typ != TokenType::Eof {
+            if self.parser.previous.typ == TokenType::Semicolon {
+                return;
+            }
+            match self.parser.current.typ {
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return => return,
+                _ => {}
+            }
+            self.advance(scanner);
+        }
+    }
+
+    fn declaration(&mut self, scanner: &mut Scanner) {
+        if self.matches(TokenType::Class, scanner) {
+            self.class_declaration(scanner);
+        } else if self.matches(TokenType::Fun, scanner) {
+            self.fun_declaration(scanner);
+        } else if self.matches(TokenType::Var, scanner) {
+            self.var_declaration(scanner);
+        } else {
+            self.statement(scanner);
+        }
+
+        if self.parser.panic_mode {
+            self.synchronize(scanner);
+        }
+    }
+
+    fn statement(&mut self, scanner: &mut Scanner) {
+        if self.matches(TokenType::Print, scanner) {
+            self.print_statement(scanner);
+        } else if self.matches(TokenType::For, scanner) {
+            self.for_statement(scanner);
+        } else if self.matches(TokenType::If, scanner) {
+            self.if_statement(scanner);
+        } else if self.matches(TokenType::Return, scanner) {
+            self.return_statement(scanner);
+        } else if self.matches(TokenType::While, scanner) {
+            self.while_statement(scanner);
+        } else if self.matches(TokenType::LeftBrace, scanner) {
+            self.begin_scope();
+            self.block(scanner);
+            self.end_scope();
+        } else {
+            self.expression_statement(scanner);
+        }
+    }
+
     fn error(&mut self, message: &str) {
         let tok = self.parser.previous.clone();
         self.error_at(&tok, message);
@@ -277,8 +1233,9 @@ impl Vm {
         } else if token.typ.is_error() {
             // nothing
         } else {
-            eprint!(" at '{message}'");
+            eprint!(" at '{}'", token.lexeme);
         }
+        eprintln!(": {message}");
         self.parser.had_error = true;
     }
 }
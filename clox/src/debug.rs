@@ -11,19 +11,68 @@ impl Chunk {
 
     pub(crate) fn disassemble_instruction(&self, offset: usize) -> usize {
         print!("{offset:04} ");
-        if offset > 0 && self.lines[offset] == self.lines[offset - 1] {
+        let line = self.get_line(offset);
+        if offset > 0 && line == self.get_line(offset - 1) {
             print!("   | ");
         } else {
-            print!("{:4} ", self.lines[offset]);
+            print!("{line:4} ");
         }
         let instruction = self.code[offset];
         match instruction.try_into() {
             Ok(OpCode::Constant) => {
                 constant_instruction("Constant", self, offset)
             }
+            Ok(OpCode::ConstantLong) => {
+                constant_instruction("ConstantLong", self, offset)
+            }
             Ok(OpCode::Nil) => simple_instruction("Nil", offset),
             Ok(OpCode::True) => simple_instruction("True", offset),
             Ok(OpCode::False) => simple_instruction("False", offset),
+            Ok(OpCode::Pop) => simple_instruction("Pop", offset),
+            Ok(OpCode::GetLocal) => byte_instruction("GetLocal", self, offset),
+            Ok(OpCode::SetLocal) => byte_instruction("SetLocal", self, offset),
+            Ok(OpCode::GetGlobal) => {
+                constant_instruction("GetGlobal", self, offset)
+            }
+            Ok(OpCode::GetGlobalLong) => {
+                constant_instruction("GetGlobalLong", self, offset)
+            }
+            Ok(OpCode::DefineGlobal) => {
+                constant_instruction("DefineGlobal", self, offset)
+            }
+            Ok(OpCode::DefineGlobalLong) => {
+                constant_instruction("DefineGlobalLong", self, offset)
+            }
+            Ok(OpCode::SetGlobal) => {
+                constant_instruction("SetGlobal", self, offset)
+            }
+            Ok(OpCode::SetGlobalLong) => {
+                constant_instruction("SetGlobalLong", self, offset)
+            }
+            Ok(OpCode::GetUpvalue) => {
+                byte_instruction("GetUpvalue", self, offset)
+            }
+            Ok(OpCode::SetUpvalue) => {
+                byte_instruction("SetUpvalue", self, offset)
+            }
+            Ok(OpCode::GetProperty) => {
+                constant_instruction("GetProperty", self, offset)
+            }
+            Ok(OpCode::GetPropertyLong) => {
+                constant_instruction("GetPropertyLong", self, offset)
+            }
+            Ok(OpCode::SetProperty) => {
+                constant_instruction("SetProperty", self, offset)
+            }
+            Ok(OpCode::SetPropertyLong) => {
+                constant_instruction("SetPropertyLong", self, offset)
+            }
+            Ok(OpCode::GetSuper) => {
+                constant_instruction("GetSuper", self, offset)
+            }
+            Ok(OpCode::GetSuperLong) => {
+                constant_instruction("GetSuperLong", self, offset)
+            }
             Ok(OpCode::Equal) => simple_instruction("Equal", offset),
             Ok(OpCode::Greater) => simple_instruction("Greater", offset),
             Ok(OpCode::Less) => simple_instruction("Less", offset),
@@ -33,7 +82,40 @@ impl Chunk {
             Ok(OpCode::Divide) => simple_instruction("Divide", offset),
             Ok(OpCode::Not) => simple_instruction("Not", offset),
             Ok(OpCode::Negate) => simple_instruction("Negate", offset),
+            Ok(OpCode::Print) => simple_instruction("Print", offset),
+            Ok(OpCode::Jump) => jump_instruction("Jump", 1, self, offset),
+            Ok(OpCode::JumpIfFalse) => {
+                jump_instruction("JumpIfFalse", 1, self, offset)
+            }
+            Ok(OpCode::Loop) => jump_instruction("Loop", -1, self, offset),
+            Ok(OpCode::Call) => byte_instruction("Call", self, offset),
+            Ok(OpCode::Invoke) => invoke_instruction("Invoke", self, offset),
+            Ok(OpCode::InvokeLong) => {
+                invoke_instruction("InvokeLong", self, offset)
+            }
+            Ok(OpCode::SuperInvoke) => {
+                invoke_instruction("SuperInvoke", self, offset)
+            }
+            Ok(OpCode::SuperInvokeLong) => {
+                invoke_instruction("SuperInvokeLong", self, offset)
+            }
+            Ok(OpCode::Closure) => closure_instruction("Closure", self, offset),
+            Ok(OpCode::ClosureLong) => {
+                closure_instruction("ClosureLong", self, offset)
+            }
+            Ok(OpCode::CloseUpvalue) => {
+                simple_instruction("CloseUpvalue", offset)
+            }
             Ok(OpCode::Return) => simple_instruction("Return", offset),
+            Ok(OpCode::Class) => constant_instruction("Class", self, offset),
+            Ok(OpCode::ClassLong) => {
+                constant_instruction("ClassLong", self, offset)
+            }
+            Ok(OpCode::Inherit) => simple_instruction("Inherit", offset),
+            Ok(OpCode::Method) => constant_instruction("Method", self, offset),
+            Ok(OpCode::MethodLong) => {
+                constant_instruction("MethodLong", self, offset)
+            }
             Err(_) => {
                 println!("Unknown opcode {instruction}");
                 offset + 1
@@ -47,12 +129,77 @@ fn simple_instruction(name: &str, offset: usize) -> usize {
     offset + 1
 }
 
+/// an instruction with a one-byte operand, such as a stack slot
+fn byte_instruction(name: &str, chunk: &Chunk, offset: usize) -> usize {
+    let slot = chunk.code[offset + 1];
+    println!("{name:<16} {slot:4}");
+    offset + 2
+}
+
+/// an instruction with a 16-bit jump offset. `sign` is -1 for jumps that go
+/// backwards. prints the offset of the jump target
+fn jump_instruction(
+    name: &str,
+    sign: isize,
+    chunk: &Chunk,
+    offset: usize,
+) -> usize {
+    let jump =
+        u16::from_be_bytes([chunk.code[offset + 1], chunk.code[offset + 2]]);
+    let target = offset as isize + 3 + sign * jump as isize;
+    println!("{name:<16} {offset:4} -> {target}");
+    offset + 3
+}
+
+/// an instruction with a constant operand naming a method followed by an
+/// argument count
+fn invoke_instruction(name: &str, chunk: &Chunk, offset: usize) -> usize {
+    let (constant, len) = constant_operand(chunk, offset);
+    let arg_count = chunk.code[offset + 1 + len];
+    let value = chunk.constants[constant];
+    println!("{name:<16} ({arg_count} args) {constant:4} '{value}'");
+    offset + 2 + len
+}
+
+/// the Closure instruction is followed by a pair of bytes for each upvalue of
+/// its function, so its length depends on that function
+fn closure_instruction(name: &str, chunk: &Chunk, offset: usize) -> usize {
+    let (constant, len) = constant_operand(chunk, offset);
+    let value = chunk.constants[constant];
+    println!("{name:<16} {constant:4} {value}");
+
+    let function = value.as_function().unwrap();
+    let mut offset = offset + 1 + len;
+    for _ in 0..function.upvalue_count {
+        let is_local = chunk.code[offset];
+        let index = chunk.code[offset + 1];
+        let kind = if is_local == 1 { "local" } else { "upvalue" };
+        println!("{offset:04}    |                     {kind} {index}");
+        offset += 2;
+    }
+    offset
+}
+
+/// the constant index operand of the instruction at `offset` and its length
+/// in bytes, which is three for the `Long` variants and one otherwise
+fn constant_operand(chunk: &Chunk, offset: usize) -> (usize, usize) {
+    let long = chunk.code[offset]
+        .try_into()
+        .is_ok_and(|op: OpCode| op.is_long());
+    if long {
+        let [hi, mid, lo] = [1, 2, 3].map(|i| chunk.code[offset + i]);
+        (u32::from_be_bytes([0, hi, mid, lo]) as usize, 3)
+    } else {
+        (chunk.code[offset + 1] as usize, 1)
+    }
+}
+
 // this might make more sense as a method since it takes a &Chunk. could just be
 // &self
 fn constant_instruction(name: &str, chunk: &Chunk, offset: usize) -> usize {
-    let constant = chunk.code[offset + 1];
-    let value = chunk.constants[constant as usize];
+    let (constant, len) = constant_operand(chunk, offset);
+    let value = chunk.constants[constant];
     // corresponds to printValue, just rely on Display impl for Value
     println!("{name:<16} {constant:4} '{value}'");
-    offset + 2
+    offset + 1 + len
 }
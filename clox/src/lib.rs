@@ -2,10 +2,19 @@
 
 static DEBUG_TRACE_EXECUTION: bool = false;
 static DEBUG_PRINT_CODE: bool = false;
+/// collect garbage before every allocation instead of waiting for the heap to
+/// grow. controlled by the `stress_gc` feature so that missing roots can be
+/// found without editing the source
+static DEBUG_STRESS_GC: bool = cfg!(feature = "stress_gc");
+static DEBUG_LOG_GC: bool = false;
 
+pub mod bytecode;
 pub mod chunk;
 pub mod compile;
 pub mod debug;
+mod memory;
 pub mod scanner;
+pub mod table;
 pub mod value;
+pub mod verify;
 pub mod vm;
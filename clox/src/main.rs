@@ -1,22 +1,63 @@
 use std::{
     env::args,
-    fs::read_to_string,
+    fs::{read, read_to_string, write},
     io::{stdin, stdout, Write},
     process::exit,
 };
 
-use clox::vm::Vm;
+use clox::{
+    bytecode::is_bytecode,
+    vm::{InterpretError, Vm},
+};
 
+fn exit_on_error(result: Result<(), InterpretError>) {
+    match result {
+        Ok(()) => {}
+        Err(InterpretError::CompileError) => exit(65),
+        Err(InterpretError::RuntimeError) => exit(70),
+    }
+}
+
+/// run either a source file or a `.loxc` file written by [compile_file]
 fn run_file(mut vm: Vm, argv: &str) {
-    let source = match read_to_string(argv) {
-        Ok(s) => s,
+    let bytes = match read(argv) {
+        Ok(b) => b,
         Err(e) => {
             eprintln!("failed to read {argv} with {e}");
             exit(74)
         }
     };
 
-    vm.interpret(source).unwrap();
+    if is_bytecode(&bytes) {
+        exit_on_error(vm.interpret_bytes(&bytes));
+        return;
+    }
+
+    let Ok(source) = String::from_utf8(bytes) else {
+        eprintln!("failed to read {argv}: not valid UTF-8");
+        exit(74)
+    };
+    exit_on_error(vm.interpret(source));
+}
+
+fn compile_file(mut vm: Vm, input: &str, output: &str) {
+    let source = match read_to_string(input) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("failed to read {input} with {e}");
+            exit(74)
+        }
+    };
+
+    let bytes = match vm.compile_to_bytes(source) {
+        Ok(b) => b,
+        Err(_) => exit(65),
+    };
+
+    if let Err(e) = write(output, bytes) {
+        eprintln!("failed to write {output} with {e}");
+        exit(74)
+    }
 }
 
 fn repl(mut vm: Vm) {
@@ -25,27 +66,30 @@ fn repl(mut vm: Vm) {
         stdout().flush().unwrap();
         let mut line = String::new();
         match stdin().read_line(&mut line) {
-            Ok(n) if n == 0 => return,
+            Ok(0) => return,
             Ok(_) => (),
             Err(e) => panic!("failed to read line from stdin with '{e:?}'"),
         }
 
-        vm.interpret(line).unwrap();
+        // errors have already been reported, so just move on to the next line
+        let _ = vm.interpret(line);
     }
 }
 
 fn main() {
     let vm = Vm::new();
 
-    let argv: Vec<_> = args().collect();
-    let argc = argv.len();
+    let args: Vec<_> = args().skip(1).collect();
+    let args: Vec<_> = args.iter().map(String::as_str).collect();
 
-    if argc == 1 {
-        repl(vm);
-    } else if argc == 2 {
-        run_file(vm, &argv[1]);
-    } else {
-        eprintln!("Usage: clox [path]");
-        exit(64);
+    match args[..] {
+        [] => repl(vm),
+        [path] => run_file(vm, path),
+        ["--compile", input, "-o", output] => compile_file(vm, input, output),
+        _ => {
+            eprintln!("Usage: clox [path]");
+            eprintln!("       clox --compile <path> -o <output>");
+            exit(64);
+        }
     }
 }
@@ -0,0 +1,172 @@
+//! the mark-sweep garbage collector from chapter 26. objects are allocated in
+//! [Vm::allocate_object], which is also where collections are triggered
+
+use crate::{
+    table::Table,
+    value::{ObjFunction, ObjKind, ObjRef, Value},
+    vm::Vm,
+    DEBUG_LOG_GC,
+};
+
+const GC_HEAP_GROW_FACTOR: usize = 2;
+
+impl Vm {
+    /// free every unreachable object. `pending` is the contents of an object
+    /// that is about to be allocated, whose references have to be kept alive
+    pub(crate) fn collect_garbage(&mut self, pending: Option<&ObjKind>) {
+        let before = self.bytes_allocated;
+        if DEBUG_LOG_GC {
+            println!("-- gc begin");
+        }
+
+        self.mark_roots();
+        if let Some(kind) = pending {
+            blacken_kind(&mut self.gray_stack, kind);
+        }
+        self.trace_references();
+        // the string table only holds onto strings that are used elsewhere
+        self.strings.remove_white();
+        self.sweep();
+
+        self.next_gc = self.bytes_allocated * GC_HEAP_GROW_FACTOR;
+
+        if DEBUG_LOG_GC {
+            println!("-- gc end");
+            println!(
+                "   collected {} bytes (from {before} to {}) next at {}",
+                before - self.bytes_allocated,
+                self.bytes_allocated,
+                self.next_gc
+            );
+        }
+    }
+
+    fn mark_roots(&mut self) {
+        let gray = &mut self.gray_stack;
+
+        for &value in &self.stack[..self.stack_top] {
+            mark_value(gray, value);
+        }
+
+        for frame in &self.frames {
+            mark_object(gray, frame.closure);
+        }
+
+        for &upvalue in &self.open_upvalues {
+            mark_object(gray, upvalue);
+        }
+
+        mark_table(gray, &self.globals);
+        if let Some(init_string) = self.init_string {
+            mark_object(gray, init_string);
+        }
+
+        // the functions being compiled aren't heap objects yet, so mark what
+        // they refer to directly
+        let mut compiler = Some(&self.compiler);
+        while let Some(c) = compiler {
+            mark_function_refs(gray, &c.function);
+            compiler = c.enclosing.as_deref();
+        }
+    }
+
+    fn trace_references(&mut self) {
+        while let Some(obj) = self.gray_stack.pop() {
+            if DEBUG_LOG_GC {
+                println!("{:?} blacken {}", obj.as_ptr(), *obj);
+            }
+            blacken_kind(&mut self.gray_stack, &obj.kind);
+        }
+    }
+
+    fn sweep(&mut self) {
+        let bytes_allocated = &mut self.bytes_allocated;
+        self.objects.retain(|&obj| {
+            if obj.is_marked.get() {
+                obj.is_marked.set(false);
+                return true;
+            }
+            if DEBUG_LOG_GC {
+                println!("{:?} free type {}", obj.as_ptr(), obj.type_name());
+            }
+            *bytes_allocated -= obj.size();
+            // SAFETY: obj wasn't marked, so nothing reachable refers to it
+            unsafe { obj.free() };
+            false
+        });
+    }
+
+    /// corresponds to freeObjects
+    pub(crate) fn free_objects(&mut self) {
+        for obj in self.objects.drain(..) {
+            // SAFETY: the vm is going away, so nothing can use these again
+            unsafe { obj.free() };
+        }
+    }
+}
+
+/// mark `obj` reachable and queue it up in `gray` to have its own references
+/// traced
+fn mark_object(gray: &mut Vec<ObjRef>, obj: ObjRef) {
+    if obj.is_marked.get() {
+        return;
+    }
+    if DEBUG_LOG_GC {
+        println!("{:?} mark {}", obj.as_ptr(), *obj);
+    }
+    obj.is_marked.set(true);
+    gray.push(obj);
+}
+
+fn mark_value(gray: &mut Vec<ObjRef>, value: Value) {
+    if let Some(obj) = value.as_obj() {
+        mark_object(gray, obj);
+    }
+}
+
+fn mark_table(gray: &mut Vec<ObjRef>, table: &Table) {
+    for (key, value) in table.iter() {
+        mark_object(gray, key);
+        mark_value(gray, value);
+    }
+}
+
+fn mark_function_refs(gray: &mut Vec<ObjRef>, function: &ObjFunction) {
+    if let Some(name) = function.name {
+        mark_object(gray, name);
+    }
+    for i in 0..function.chunk.constants.len() {
+        mark_value(gray, function.chunk.constants[i]);
+    }
+}
+
+/// mark everything referred to by an object with contents `kind`
+fn blacken_kind(gray: &mut Vec<ObjRef>, kind: &ObjKind) {
+    match kind {
+        ObjKind::BoundMethod(bound) => {
+            mark_value(gray, bound.receiver);
+            mark_object(gray, bound.method);
+        }
+        ObjKind::Class(class) => {
+            mark_object(gray, class.name);
+            mark_table(gray, &class.methods.borrow());
+        }
+        ObjKind::Closure(closure) => {
+            mark_object(gray, closure.function);
+            for &upvalue in &closure.upvalues {
+                mark_object(gray, upvalue);
+            }
+        }
+        ObjKind::Function(function) => mark_function_refs(gray, function),
+        ObjKind::Instance(instance) => {
+            mark_object(gray, instance.class);
+            mark_table(gray, &instance.fields.borrow());
+        }
+        ObjKind::Upvalue(upvalue) => {
+            if let Some(closed) = upvalue.closed.get() {
+                mark_value(gray, closed);
+            }
+        }
+        ObjKind::Native(_) | ObjKind::String(_) => {}
+    }
+}
@@ -6,28 +6,36 @@ pub(crate) struct Scanner {
     start: usize,
     current: usize,
     line: usize,
+    /// the index in `source` where the current line begins
+    line_start: usize,
+    /// the column of `start`, saved before scanning the token in case it runs
+    /// over multiple lines
+    start_column: usize,
 }
 
+/// unlike the C version, which points into the source, tokens own a copy of
+/// their lexeme. for error tokens the lexeme is the error message
 #[derive(Clone, Default)]
 pub(crate) struct Token {
     pub(crate) typ: TokenType,
-    pub(crate) start: usize,
-    pub(crate) length: usize,
+    pub(crate) lexeme: String,
     pub(crate) line: usize,
+    /// the one-based column where the lexeme starts
+    pub(crate) column: usize,
 }
 
 impl Token {
     pub(crate) fn new(
         typ: TokenType,
-        start: usize,
-        length: usize,
+        lexeme: String,
         line: usize,
+        column: usize,
     ) -> Self {
         Self {
             typ,
-            start,
-            length,
+            lexeme,
             line,
+            column,
         }
     }
 }
@@ -53,18 +61,15 @@ impl Scanner {
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            start_column: 1,
         }
     }
 
-    pub(crate) fn get_token(&self, token: &Token) -> String {
-        self.source[token.start..token.start + token.length]
-            .iter()
-            .collect()
-    }
-
     pub(crate) fn scan_token(&mut self) -> Token {
         self.skip_whitespace();
         self.start = self.current;
+        self.start_column = self.start - self.line_start + 1;
 
         if self.at_end() {
             return self.make_token(TokenType::Eof);
@@ -119,11 +124,17 @@ impl Scanner {
     }
 
     fn make_token(&mut self, typ: TokenType) -> Token {
-        Token::new(typ, self.start, self.current - self.start, self.line)
+        let lexeme = self.source[self.start..self.current].iter().collect();
+        Token::new(typ, lexeme, self.line, self.start_column)
     }
 
     fn error_token(&self, arg: &str) -> Token {
-        Token::new(TokenType::Error, 0, arg.len(), self.line)
+        Token::new(
+            TokenType::Error,
+            arg.to_owned(),
+            self.line,
+            self.start_column,
+        )
     }
 
     fn at_end(&self) -> bool {
@@ -154,16 +165,12 @@ impl Scanner {
                     self.advance();
                 }
                 '\n' => {
-                    self.line += 1;
+                    self.new_line();
                     self.advance();
                 }
-                '/' => {
-                    if self.peek_next() == '/' {
-                        while self.peek() != '\n' && !self.at_end() {
-                            self.advance();
-                        }
-                    } else {
-                        return;
+                '/' if self.peek_next() == '/' => {
+                    while self.peek() != '\n' && !self.at_end() {
+                        self.advance();
                     }
                 }
                 _ => return,
@@ -288,7 +295,7 @@ impl Scanner {
     fn string(&mut self) -> Token {
         while self.peek() != '"' && !self.at_end() {
             if self.peek() == '\n' {
-                self.line += 1;
+                self.new_line();
             }
             self.advance();
         }
@@ -301,6 +308,12 @@ impl Scanner {
         self.make_token(TokenType::String)
     }
 
+    /// record that the next character, a newline, ends the current line
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.line_start = self.current + 1;
+    }
+
     fn peek(&self) -> char {
         if self.at_end() {
             return '\0';
@@ -0,0 +1,372 @@
+//! the hash table from chapter 20: open addressing with linear probing, keyed
+//! by string objects. since strings are interned, keys can be compared by
+//! pointer once they're in the table
+
+use crate::value::{ObjRef, Value};
+
+const TABLE_MAX_LOAD: f64 = 0.75;
+
+#[derive(Clone, Copy, Default)]
+struct Entry {
+    /// `None` for both empty slots and tombstones. a tombstone is told apart
+    /// by having a `true` value instead of nil
+    key: Option<ObjRef>,
+    value: Value,
+}
+
+impl Entry {
+    fn is_tombstone(&self) -> bool {
+        self.key.is_none() && !self.value.is_nil()
+    }
+}
+
+#[derive(Default)]
+pub struct Table {
+    /// number of occupied entries, including tombstones
+    count: usize,
+    entries: Vec<Entry>,
+}
+
+/// corresponds to the FNV-1a hashString function
+pub(crate) fn hash_string(chars: &str) -> u32 {
+    let mut hash = 2166136261u32;
+    for b in chars.bytes() {
+        hash ^= b as u32;
+        hash = hash.wrapping_mul(16777619);
+    }
+    hash
+}
+
+fn hash_of(key: ObjRef) -> u32 {
+    key.as_string().unwrap().hash
+}
+
+/// find the slot that either holds `key` or where `key` should be inserted.
+/// returns the first tombstone passed along the way, if any, so that it can be
+/// reused. `entries` must not be empty and must contain at least one truly
+/// empty slot, which the load factor guarantees
+fn find_entry(entries: &[Entry], key: ObjRef) -> usize {
+    let capacity = entries.len();
+    let mut index = hash_of(key) as usize % capacity;
+    let mut tombstone = None;
+    loop {
+        let entry = &entries[index];
+        match entry.key {
+            None if entry.is_tombstone() => {
+                tombstone.get_or_insert(index);
+            }
+            None => return tombstone.unwrap_or(index),
+            Some(k) if k == key => return index,
+            Some(_) => {}
+        }
+        index = (index + 1) % capacity;
+    }
+}
+
+impl Table {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the number of live entries, not counting tombstones
+    pub fn len(&self) -> usize {
+        self.entries.iter().filter(|e| e.key.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, key: ObjRef) -> Option<Value> {
+        if self.count == 0 {
+            return None;
+        }
+        let entry = &self.entries[find_entry(&self.entries, key)];
+        entry.key.map(|_| entry.value)
+    }
+
+    /// insert `key` with `value`, overwriting any existing value. returns true
+    /// if `key` was not already present
+    pub fn set(&mut self, key: ObjRef, value: Value) -> bool {
+        if (self.count + 1) as f64 > self.capacity() as f64 * TABLE_MAX_LOAD {
+            let capacity = grow_capacity(self.capacity());
+            self.adjust_capacity(capacity);
+        }
+
+        let index = find_entry(&self.entries, key);
+        let entry = &mut self.entries[index];
+        let is_new_key = entry.key.is_none();
+        // reusing a tombstone doesn't change the count since tombstones were
+        // already counted
+        if is_new_key && entry.value.is_nil() {
+            self.count += 1;
+        }
+        entry.key = Some(key);
+        entry.value = value;
+        is_new_key
+    }
+
+    /// remove `key`, leaving a tombstone in its place so that probe sequences
+    /// passing through it still work. returns true if `key` was present
+    pub fn delete(&mut self, key: ObjRef) -> bool {
+        if self.count == 0 {
+            return false;
+        }
+        let index = find_entry(&self.entries, key);
+        let entry = &mut self.entries[index];
+        if entry.key.is_none() {
+            return false;
+        }
+        entry.key = None;
+        entry.value = Value::boolean(true);
+        true
+    }
+
+    /// corresponds to tableAddAll, copying every entry of `self` into `to`
+    pub fn add_all(&self, to: &mut Table) {
+        for entry in &self.entries {
+            if let Some(key) = entry.key {
+                to.set(key, entry.value);
+            }
+        }
+    }
+
+    /// look up an interned string by its contents rather than by pointer. this
+    /// is the one place keys are compared by value
+    pub(crate) fn find_string(&self, chars: &str, hash: u32) -> Option<ObjRef> {
+        if self.count == 0 {
+            return None;
+        }
+        let capacity = self.capacity();
+        let mut index = hash as usize % capacity;
+        loop {
+            let entry = &self.entries[index];
+            match entry.key {
+                // stop at a truly empty slot, but keep going past tombstones
+                None if !entry.is_tombstone() => return None,
+                None => {}
+                Some(key) => {
+                    let s = key.as_string().unwrap();
+                    if s.hash == hash && s.chars == chars {
+                        return Some(key);
+                    }
+                }
+            }
+            index = (index + 1) % capacity;
+        }
+    }
+
+    /// iterate over the live entries
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (ObjRef, Value)> + '_ {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.key.map(|key| (key, entry.value)))
+    }
+
+    /// delete every entry whose key wasn't marked by the garbage collector.
+    /// used to make the string table weak
+    pub(crate) fn remove_white(&mut self) {
+        for i in 0..self.entries.len() {
+            if let Some(key) = self.entries[i].key {
+                if !key.is_marked.get() {
+                    self.delete(key);
+                }
+            }
+        }
+    }
+
+    /// rebuild the table with `capacity` slots, dropping tombstones
+    fn adjust_capacity(&mut self, capacity: usize) {
+        let mut entries = vec![Entry::default(); capacity];
+        self.count = 0;
+        for entry in &self.entries {
+            let Some(key) = entry.key else {
+                continue;
+            };
+            let index = find_entry(&entries, key);
+            entries[index] = *entry;
+            self.count += 1;
+        }
+        self.entries = entries;
+    }
+}
+
+/// corresponds to the GROW_CAPACITY macro
+fn grow_capacity(capacity: usize) -> usize {
+    if capacity < 8 {
+        8
+    } else {
+        capacity * 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::{Obj, ObjKind, ObjString};
+
+    /// owns the string objects used as keys in a test and frees them at the
+    /// end
+    #[derive(Default)]
+    struct Keys(Vec<ObjRef>);
+
+    impl Keys {
+        fn key(&mut self, chars: &str) -> ObjRef {
+            self.with_hash(chars, hash_string(chars))
+        }
+
+        /// make a key with a chosen hash, for forcing collisions
+        fn with_hash(&mut self, chars: &str, hash: u32) -> ObjRef {
+            let key = ObjRef::new(Obj::new(ObjKind::String(ObjString {
+                chars: chars.to_owned(),
+                hash,
+            })));
+            self.0.push(key);
+            key
+        }
+    }
+
+    impl Drop for Keys {
+        fn drop(&mut self) {
+            for key in self.0.drain(..) {
+                unsafe { key.free() };
+            }
+        }
+    }
+
+    #[test]
+    fn insert_and_get() {
+        let mut keys = Keys::default();
+        let mut table = Table::new();
+        let a = keys.key("a");
+        let b = keys.key("b");
+
+        assert_eq!(table.get(a), None);
+        assert!(table.set(a, Value::number(1.0)));
+        assert!(table.set(b, Value::number(2.0)));
+        assert_eq!(table.get(a), Some(Value::number(1.0)));
+        assert_eq!(table.get(b), Some(Value::number(2.0)));
+
+        // overwriting is not a new key
+        assert!(!table.set(a, Value::number(3.0)));
+        assert_eq!(table.get(a), Some(Value::number(3.0)));
+        assert_eq!(table.len(), 2);
+    }
+
+    #[test]
+    fn keys_compare_by_pointer() {
+        let mut keys = Keys::default();
+        let mut table = Table::new();
+        let a = keys.key("same");
+        let b = keys.key("same");
+        table.set(a, Value::number(1.0));
+        assert_eq!(table.get(b), None);
+        assert_eq!(table.find_string("same", hash_string("same")), Some(a));
+        assert_eq!(table.find_string("other", hash_string("other")), None);
+    }
+
+    #[test]
+    fn delete() {
+        let mut keys = Keys::default();
+        let mut table = Table::new();
+        let a = keys.key("a");
+        let b = keys.key("b");
+
+        assert!(!table.delete(a));
+        table.set(a, Value::nil());
+        table.set(b, Value::nil());
+        assert!(table.delete(a));
+        assert!(!table.delete(a));
+        assert_eq!(table.get(a), None);
+        assert_eq!(table.get(b), Some(Value::nil()));
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn delete_keeps_probe_sequence() {
+        let mut keys = Keys::default();
+        let mut table = Table::new();
+        let a = keys.with_hash("a", 0);
+        let b = keys.with_hash("b", 0);
+        let c = keys.with_hash("c", 0);
+        table.set(a, Value::number(1.0));
+        table.set(b, Value::number(2.0));
+        table.set(c, Value::number(3.0));
+
+        // b sits between a and c in the same probe sequence. deleting it must
+        // leave a tombstone so that c is still found
+        table.delete(b);
+        assert_eq!(table.get(c), Some(Value::number(3.0)));
+        assert_eq!(table.find_string("c", 0), Some(c));
+
+        // and the tombstone is reused rather than counted again
+        let count = table.count;
+        assert!(table.set(b, Value::number(4.0)));
+        assert_eq!(table.count, count);
+        assert_eq!(table.get(b), Some(Value::number(4.0)));
+        assert_eq!(table.get(c), Some(Value::number(3.0)));
+    }
+
+    #[test]
+    fn grow() {
+        let mut keys = Keys::default();
+        let mut table = Table::new();
+        assert_eq!(table.capacity(), 0);
+
+        let names: Vec<_> = (0..100).map(|i| format!("key{i}")).collect();
+        let refs: Vec<_> = names.iter().map(|n| keys.key(n)).collect();
+        for (i, &key) in refs.iter().enumerate() {
+            table.set(key, Value::number(i as f64));
+            assert!(
+                table.count as f64 <= table.capacity() as f64 * TABLE_MAX_LOAD
+            );
+        }
+        assert_eq!(table.capacity(), 256);
+        assert_eq!(table.len(), 100);
+        for (i, &key) in refs.iter().enumerate() {
+            assert_eq!(table.get(key), Some(Value::number(i as f64)));
+        }
+    }
+
+    #[test]
+    fn grow_drops_tombstones() {
+        let mut keys = Keys::default();
+        let mut table = Table::new();
+        let refs: Vec<_> =
+            (0..6).map(|i| keys.key(&format!("key{i}"))).collect();
+        for &key in &refs {
+            table.set(key, Value::nil());
+        }
+        for &key in &refs[..5] {
+            table.delete(key);
+        }
+        assert_eq!(table.count, 6);
+
+        // the seventh entry pushes the table past its load factor
+        let extra = keys.key("extra");
+        table.set(extra, Value::nil());
+        assert_eq!(table.capacity(), 16);
+        assert_eq!(table.count, 2);
+        assert_eq!(table.get(refs[5]), Some(Value::nil()));
+        assert_eq!(table.get(extra), Some(Value::nil()));
+    }
+
+    #[test]
+    fn add_all() {
+        let mut keys = Keys::default();
+        let mut from = Table::new();
+        let mut to = Table::new();
+        let a = keys.key("a");
+        let b = keys.key("b");
+        from.set(a, Value::number(1.0));
+        from.set(b, Value::number(2.0));
+        from.delete(b);
+        from.add_all(&mut to);
+        assert_eq!(to.get(a), Some(Value::number(1.0)));
+        assert_eq!(to.get(b), None);
+    }
+}
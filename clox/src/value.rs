@@ -1,89 +1,58 @@
 use std::{fmt::Display, ops::Index};
 
-#[derive(Default, Clone, Copy, Debug)]
-pub enum Value {
-    Bool(bool),
-    #[default]
-    Nil,
-    Number(f64),
-}
+pub mod object;
+pub use object::*;
 
-impl Value {
-    pub(crate) fn boolean(v: bool) -> Self {
-        Self::Bool(v)
-    }
+#[cfg(not(feature = "nan_boxing"))]
+mod tagged;
+#[cfg(not(feature = "nan_boxing"))]
+pub use tagged::Value;
 
-    pub(crate) fn nil() -> Self {
-        Self::Nil
-    }
-
-    pub(crate) fn number(v: f64) -> Self {
-        Self::Number(v)
-    }
+#[cfg(feature = "nan_boxing")]
+mod nan_boxed;
+#[cfg(feature = "nan_boxing")]
+pub use nan_boxed::Value;
 
+// the parts of Value that don't depend on its representation
+impl Value {
     pub(crate) fn is_falsey(&self) -> bool {
-        self.is_nil() || (self.is_bool() && !self.as_bool().unwrap())
+        self.is_nil() || self.as_bool() == Some(false)
     }
 
-    pub fn as_bool(&self) -> Option<&bool> {
-        if let Self::Bool(v) = self {
-            Some(v)
-        } else {
-            None
-        }
-    }
-
-    /// Returns `true` if the value is [`Bool`].
-    ///
-    /// [`Bool`]: Value::Bool
+    /// Returns `true` if the value is a string object
     #[must_use]
-    pub fn is_bool(&self) -> bool {
-        matches!(self, Self::Bool(..))
+    pub fn is_string(&self) -> bool {
+        self.object().is_some_and(|o| o.is_string())
     }
 
-    /// Returns `true` if the value is [`Nil`].
-    ///
-    /// [`Nil`]: Value::Nil
-    #[must_use]
-    pub fn is_nil(&self) -> bool {
-        matches!(self, Self::Nil)
+    pub fn as_string(&self) -> Option<&ObjString> {
+        self.object().and_then(|o| o.as_string())
     }
 
-    /// Returns `true` if the value is [`Number`].
-    ///
-    /// [`Number`]: Value::Number
-    #[must_use]
-    pub fn is_number(&self) -> bool {
-        matches!(self, Self::Number(..))
+    pub fn as_class(&self) -> Option<&ObjClass> {
+        self.object().and_then(|o| o.as_class())
     }
 
-    pub fn as_number(&self) -> Option<&f64> {
-        if let Self::Number(v) = self {
-            Some(v)
-        } else {
-            None
-        }
+    pub fn as_function(&self) -> Option<&ObjFunction> {
+        self.object().and_then(|o| o.as_function())
     }
-}
 
-impl PartialEq for Value {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Value::Bool(a), Value::Bool(b)) => a == b,
-            (Value::Nil, Value::Nil) => true,
-            (Value::Number(a), Value::Number(b)) => a == b,
-            _ => false,
-        }
+    pub fn as_instance(&self) -> Option<&ObjInstance> {
+        self.object().and_then(|o| o.as_instance())
     }
 }
 
 // corresponds to printValue
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Value::Bool(b) => write!(f, "{b}"),
-            Value::Nil => write!(f, "nil"),
-            Value::Number(n) => write!(f, "{n}"),
+        if let Some(b) = self.as_bool() {
+            write!(f, "{b}")
+        } else if self.is_nil() {
+            write!(f, "nil")
+        } else if let Some(n) = self.as_number() {
+            write!(f, "{n}")
+        } else {
+            write!(f, "{}", *self.as_obj().unwrap())
         }
     }
 }
@@ -122,3 +91,166 @@ impl Index<usize> for ValueArray {
         &self.values[index]
     }
 }
+
+// these run against whichever representation is selected, so run them both
+// with and without the nan_boxing feature
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::Vm;
+
+    const NUMBERS: [f64; 9] = [
+        0.0,
+        -0.0,
+        1.0,
+        -2.5,
+        1e300,
+        f64::MIN_POSITIVE,
+        f64::MAX,
+        f64::INFINITY,
+        f64::NEG_INFINITY,
+    ];
+
+    /// one value of each kind, as (value, is_bool, is_nil, is_number, is_obj).
+    /// the string is left on the vm's stack so that it survives later
+    /// allocations under the stress_gc feature
+    fn kinds(vm: &mut Vm) -> Vec<(Value, bool, bool, bool, bool)> {
+        let s = vm.copy_string("hello");
+        vm.push(Value::obj(s));
+        vec![
+            (Value::boolean(true), true, false, false, false),
+            (Value::boolean(false), true, false, false, false),
+            (Value::nil(), false, true, false, false),
+            (Value::number(1.5), false, false, true, false),
+            (Value::number(f64::NAN), false, false, true, false),
+            (Value::obj(s), false, false, false, true),
+        ]
+    }
+
+    #[test]
+    fn predicates() {
+        let mut vm = Vm::new();
+        for (value, is_bool, is_nil, is_number, is_obj) in kinds(&mut vm) {
+            assert_eq!(value.is_bool(), is_bool, "{value:?}");
+            assert_eq!(value.is_nil(), is_nil, "{value:?}");
+            assert_eq!(value.is_number(), is_number, "{value:?}");
+            assert_eq!(value.is_obj(), is_obj, "{value:?}");
+            assert_eq!(value.as_bool().is_some(), is_bool);
+            assert_eq!(value.as_number().is_some(), is_number);
+            assert_eq!(value.as_obj().is_some(), is_obj);
+        }
+        assert!(Value::default().is_nil());
+    }
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(Value::boolean(true).as_bool(), Some(true));
+        assert_eq!(Value::boolean(false).as_bool(), Some(false));
+        for n in NUMBERS {
+            let got = Value::number(n).as_number().unwrap();
+            assert_eq!(got.to_bits(), n.to_bits());
+        }
+
+        let mut vm = Vm::new();
+        let s = vm.copy_string("hello");
+        let value = Value::obj(s);
+        assert_eq!(value.as_obj(), Some(s));
+        assert!(value.is_string());
+        assert_eq!(value.as_string().unwrap().chars, "hello");
+        assert!(!Value::number(1.0).is_string());
+    }
+
+    #[test]
+    fn nan() {
+        // computed at runtime so that we get whatever NaN the hardware makes
+        let zero = std::hint::black_box(0.0);
+        for n in [f64::NAN, -f64::NAN, zero / zero] {
+            let value = Value::number(n);
+            assert!(value.is_number());
+            assert!(!value.is_obj());
+            assert!(value.as_number().unwrap().is_nan());
+            assert_ne!(value, value);
+        }
+    }
+
+    #[test]
+    fn equality() {
+        let mut vm = Vm::new();
+        let values: Vec<_> = kinds(&mut vm).into_iter().map(|k| k.0).collect();
+        for (i, a) in values.iter().enumerate() {
+            for (j, b) in values.iter().enumerate() {
+                let nan = a.as_number().is_some_and(f64::is_nan);
+                assert_eq!(a == b, i == j && !nan, "{a:?} == {b:?}");
+            }
+        }
+
+        assert_eq!(Value::number(0.0), Value::number(-0.0));
+        assert_ne!(Value::number(0.0), Value::boolean(false));
+        assert_ne!(Value::number(0.0), Value::nil());
+
+        // interned strings are the same object, other objects are not equal
+        let a = Value::obj(vm.copy_string("a"));
+        vm.push(a);
+        assert_eq!(a, Value::obj(vm.copy_string("a")));
+        assert_ne!(a, Value::obj(vm.copy_string("b")));
+    }
+
+    #[test]
+    fn display() {
+        let mut vm = Vm::new();
+        let s = vm.copy_string("hello");
+        let cases = [
+            (Value::boolean(true), "true"),
+            (Value::boolean(false), "false"),
+            (Value::nil(), "nil"),
+            (Value::number(1.0), "1"),
+            (Value::number(-2.5), "-2.5"),
+            (Value::number(f64::NAN), "NaN"),
+            (Value::obj(s), "hello"),
+        ];
+        for (value, want) in cases {
+            assert_eq!(value.to_string(), want);
+        }
+    }
+
+    #[test]
+    fn falsey() {
+        assert!(Value::nil().is_falsey());
+        assert!(Value::boolean(false).is_falsey());
+        assert!(!Value::boolean(true).is_falsey());
+        assert!(!Value::number(0.0).is_falsey());
+        let mut vm = Vm::new();
+        assert!(!Value::obj(vm.copy_string("")).is_falsey());
+    }
+
+    #[test]
+    fn value_array() {
+        let mut vm = Vm::new();
+        let mut array = ValueArray::new();
+        let values = kinds(&mut vm);
+        for &(value, ..) in &values {
+            array.push(value);
+        }
+        assert_eq!(array.len(), values.len());
+        for (i, &(value, ..)) in values.iter().enumerate() {
+            assert_eq!(array[i].to_string(), value.to_string());
+            assert_eq!(array[i].is_obj(), value.is_obj());
+        }
+    }
+
+    #[test]
+    fn stack() {
+        let mut vm = Vm::new();
+        let values = kinds(&mut vm);
+        for &(value, ..) in &values {
+            vm.push(value);
+        }
+        for &(value, ..) in values.iter().rev() {
+            let got = vm.pop();
+            assert_eq!(got.to_string(), value.to_string());
+            assert_eq!(got.as_obj(), value.as_obj());
+        }
+        vm.pop(); // the string rooted by kinds
+        assert!(vm.stack.iter().all(Value::is_nil));
+    }
+}
@@ -0,0 +1,127 @@
+//! the NaN-boxed representation of [Value], enabled by the `nan_boxing`
+//! feature. every value is a single `u64`: numbers are stored as their own
+//! bits, and everything else hides in the unused payload of a quiet NaN. this
+//! relies on object pointers fitting in the low 48 bits, which holds on the
+//! 64-bit platforms we care about
+
+use std::fmt::Debug;
+
+use super::{Obj, ObjRef};
+
+/// the sign bit, set only for object pointers
+const SIGN_BIT: u64 = 0x8000000000000000;
+
+/// the exponent bits, the quiet NaN bit, and one more bit to stay clear of the
+/// "QNaN Floating-Point Indefinite" value Intel produces for things like 0/0
+const QNAN: u64 = 0x7ffc000000000000;
+
+const TAG_NIL: u64 = 1;
+const TAG_FALSE: u64 = 2;
+const TAG_TRUE: u64 = 3;
+
+const NIL_VAL: u64 = QNAN | TAG_NIL;
+const FALSE_VAL: u64 = QNAN | TAG_FALSE;
+const TRUE_VAL: u64 = QNAN | TAG_TRUE;
+
+#[derive(Clone, Copy)]
+pub struct Value(u64);
+
+impl Value {
+    pub(crate) fn boolean(v: bool) -> Self {
+        Self(if v { TRUE_VAL } else { FALSE_VAL })
+    }
+
+    pub(crate) fn nil() -> Self {
+        Self(NIL_VAL)
+    }
+
+    pub(crate) fn number(v: f64) -> Self {
+        Self(v.to_bits())
+    }
+
+    pub(crate) fn obj(v: ObjRef) -> Self {
+        Self(SIGN_BIT | QNAN | v.as_ptr() as u64)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        self.is_bool().then_some(self.0 == TRUE_VAL)
+    }
+
+    /// Returns `true` if the value is a boolean
+    #[must_use]
+    pub fn is_bool(&self) -> bool {
+        // true and false differ only in their lowest bit
+        (self.0 | 1) == TRUE_VAL
+    }
+
+    /// Returns `true` if the value is nil
+    #[must_use]
+    pub fn is_nil(&self) -> bool {
+        self.0 == NIL_VAL
+    }
+
+    /// Returns `true` if the value is a number
+    #[must_use]
+    pub fn is_number(&self) -> bool {
+        (self.0 & QNAN) != QNAN
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        self.is_number().then(|| f64::from_bits(self.0))
+    }
+
+    /// Returns `true` if the value is an object
+    #[must_use]
+    pub fn is_obj(&self) -> bool {
+        (self.0 & (QNAN | SIGN_BIT)) == (QNAN | SIGN_BIT)
+    }
+
+    pub fn as_obj(&self) -> Option<ObjRef> {
+        if !self.is_obj() {
+            return None;
+        }
+        let ptr = (self.0 & !(SIGN_BIT | QNAN)) as *mut Obj;
+        // SAFETY: the only way to set these bits is Value::obj, which was
+        // given a valid ObjRef
+        Some(unsafe { ObjRef::from_ptr(ptr) })
+    }
+
+    /// borrow the object this value points to, if any
+    pub(crate) fn object(&self) -> Option<&Obj> {
+        // SAFETY: see the docs on ObjRef
+        self.as_obj().map(|o| unsafe { &*o.as_ptr() })
+    }
+}
+
+impl Default for Value {
+    fn default() -> Self {
+        Self::nil()
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        // compare numbers as numbers so that NaN is still not equal to itself.
+        // everything else is equal exactly when the bits are, and strings are
+        // interned, so equal strings are the same object
+        match (self.as_number(), other.as_number()) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.0 == other.0,
+        }
+    }
+}
+
+// match the derived output of the enum representation
+impl Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(b) = self.as_bool() {
+            f.debug_tuple("Bool").field(&b).finish()
+        } else if self.is_nil() {
+            write!(f, "Nil")
+        } else if let Some(n) = self.as_number() {
+            f.debug_tuple("Number").field(&n).finish()
+        } else {
+            f.debug_tuple("Obj").field(&self.as_obj().unwrap()).finish()
+        }
+    }
+}
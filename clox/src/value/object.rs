@@ -0,0 +1,348 @@
+//! heap-allocated objects. the C version embeds an `Obj` header at the start of
+//! each object struct and casts between them. here [Obj] is the header and the
+//! concrete object lives in its [ObjKind]
+
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Display,
+    ops::Deref,
+    ptr::NonNull,
+};
+
+use super::Value;
+
+use crate::{
+    chunk::{Chunk, LineRun},
+    table::{hash_string, Table},
+    vm::Vm,
+    DEBUG_LOG_GC, DEBUG_STRESS_GC,
+};
+
+pub struct Obj {
+    /// set during the mark phase of garbage collection for objects that are
+    /// still reachable
+    pub(crate) is_marked: Cell<bool>,
+    pub(crate) kind: ObjKind,
+}
+
+pub enum ObjKind {
+    BoundMethod(ObjBoundMethod),
+    Class(ObjClass),
+    Closure(ObjClosure),
+    Function(ObjFunction),
+    Instance(ObjInstance),
+    Native(ObjNative),
+    String(ObjString),
+    Upvalue(ObjUpvalue),
+}
+
+/// a method closure paired with the instance it was accessed on
+pub struct ObjBoundMethod {
+    pub(crate) receiver: Value,
+    pub(crate) method: ObjRef,
+}
+
+pub struct ObjClass {
+    pub(crate) name: ObjRef,
+    /// method closures by name. the table is filled in by `Method`
+    /// instructions after the class is created, hence the `RefCell`
+    pub(crate) methods: RefCell<Table>,
+}
+
+/// a function together with the variables it captured. functions only exist at
+/// runtime wrapped in closures
+pub struct ObjClosure {
+    pub(crate) function: ObjRef,
+    pub(crate) upvalues: Vec<ObjRef>,
+}
+
+#[derive(Default)]
+pub struct ObjFunction {
+    pub(crate) arity: usize,
+    pub(crate) upvalue_count: usize,
+    pub(crate) chunk: Chunk,
+    /// `None` for the implicit function wrapping top-level code
+    pub(crate) name: Option<ObjRef>,
+}
+
+pub struct ObjInstance {
+    pub(crate) class: ObjRef,
+    pub(crate) fields: RefCell<Table>,
+}
+
+/// a function implemented in Rust. it receives the call's arguments and can't
+/// fail
+pub type NativeFn = fn(&[Value]) -> Value;
+
+pub struct ObjNative {
+    pub(crate) function: NativeFn,
+}
+
+/// a captured variable. while the variable is still live on the stack the
+/// upvalue is open and refers to its stack slot. once the variable goes out of
+/// scope, the value is moved into `closed`. the C version does this by
+/// repointing `location` at the `closed` field
+pub struct ObjUpvalue {
+    pub(crate) location: usize,
+    pub(crate) closed: Cell<Option<Value>>,
+}
+
+pub struct ObjString {
+    pub(crate) chars: String,
+    /// cached so that table lookups don't have to rehash the string
+    pub(crate) hash: u32,
+}
+
+/// a pointer to an [Obj] owned by the [Vm](crate::vm::Vm). this is `Copy` so
+/// that [Value](crate::value::Value) can stay `Copy`, which means nothing stops
+/// an `ObjRef` from outliving its object. the vm only frees objects that are no
+/// longer reachable from any value, so dereferencing one we got out of a value
+/// is fine
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ObjRef(NonNull<Obj>);
+
+impl ObjRef {
+    /// move `obj` to the heap and return a pointer to it. the caller is
+    /// responsible for eventually passing the result to [ObjRef::free]
+    pub(crate) fn new(obj: Obj) -> Self {
+        Self(NonNull::from(Box::leak(Box::new(obj))))
+    }
+
+    /// # Safety
+    ///
+    /// `self` must have come from [ObjRef::new] and must not be used again
+    /// after this call, including through copies
+    pub(crate) unsafe fn free(self) {
+        drop(Box::from_raw(self.0.as_ptr()));
+    }
+}
+
+impl Deref for ObjRef {
+    type Target = Obj;
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: see the type-level docs
+        unsafe { self.0.as_ref() }
+    }
+}
+
+impl Obj {
+    pub(crate) fn new(kind: ObjKind) -> Self {
+        Self {
+            is_marked: Cell::new(false),
+            kind,
+        }
+    }
+
+    /// an estimate of the memory owned by this object, for deciding when to
+    /// collect garbage. this has to stay the same for the life of the object
+    /// since it's used both when allocating and freeing it, so the growable
+    /// method and field tables aren't counted
+    pub(crate) fn size(&self) -> usize {
+        let owned = match &self.kind {
+            ObjKind::BoundMethod(_)
+            | ObjKind::Class(_)
+            | ObjKind::Instance(_) => 0,
+            ObjKind::Closure(closure) => {
+                closure.upvalues.capacity() * size_of::<ObjRef>()
+            }
+            ObjKind::Function(function) => {
+                let chunk = &function.chunk;
+                chunk.code.capacity()
+                    + chunk.constants.len() * size_of::<Value>()
+                    + chunk.lines.capacity() * size_of::<LineRun>()
+                    + chunk.columns.packed.capacity()
+            }
+            ObjKind::Native(_) | ObjKind::Upvalue(_) => 0,
+            ObjKind::String(s) => s.chars.capacity(),
+        };
+        size_of::<Obj>() + owned
+    }
+
+    /// the name of the object's type, for logging
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self.kind {
+            ObjKind::BoundMethod(_) => "bound method",
+            ObjKind::Class(_) => "class",
+            ObjKind::Closure(_) => "closure",
+            ObjKind::Function(_) => "function",
+            ObjKind::Instance(_) => "instance",
+            ObjKind::Native(_) => "native",
+            ObjKind::String(_) => "string",
+            ObjKind::Upvalue(_) => "upvalue",
+        }
+    }
+
+    pub fn as_bound_method(&self) -> Option<&ObjBoundMethod> {
+        if let ObjKind::BoundMethod(v) = &self.kind {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_class(&self) -> Option<&ObjClass> {
+        if let ObjKind::Class(v) = &self.kind {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_closure(&self) -> Option<&ObjClosure> {
+        if let ObjKind::Closure(v) = &self.kind {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_function(&self) -> Option<&ObjFunction> {
+        if let ObjKind::Function(v) = &self.kind {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_instance(&self) -> Option<&ObjInstance> {
+        if let ObjKind::Instance(v) = &self.kind {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_native(&self) -> Option<&ObjNative> {
+        if let ObjKind::Native(v) = &self.kind {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_string(&self) -> Option<&ObjString> {
+        if let ObjKind::String(v) = &self.kind {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_upvalue(&self) -> Option<&ObjUpvalue> {
+        if let ObjKind::Upvalue(v) = &self.kind {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the object is a [`String`].
+    ///
+    /// [`String`]: ObjKind::String
+    #[must_use]
+    pub fn is_string(&self) -> bool {
+        matches!(self.kind, ObjKind::String(..))
+    }
+}
+
+// corresponds to printObject
+impl Display for Obj {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ObjKind::BoundMethod(bound) => write!(f, "{}", *bound.method),
+            ObjKind::Class(class) => write!(f, "{}", *class.name),
+            ObjKind::Closure(closure) => write!(f, "{}", *closure.function),
+            ObjKind::Function(fun) => write!(f, "{fun}"),
+            ObjKind::Instance(instance) => {
+                let class = instance.class.as_class().unwrap();
+                write!(f, "{} instance", *class.name)
+            }
+            ObjKind::Native(_) => write!(f, "<native fn>"),
+            ObjKind::String(s) => write!(f, "{}", s.chars),
+            ObjKind::Upvalue(_) => write!(f, "upvalue"),
+        }
+    }
+}
+
+impl Display for ObjFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.name {
+            Some(name) => write!(f, "<fn {}>", *name),
+            None => write!(f, "<script>"),
+        }
+    }
+}
+
+impl ObjRef {
+    /// the address of the object, for logging
+    pub(crate) fn as_ptr(&self) -> *const Obj {
+        self.0.as_ptr()
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must have come from [ObjRef::as_ptr] on an object that hasn't
+    /// been freed
+    #[cfg(feature = "nan_boxing")]
+    pub(crate) unsafe fn from_ptr(ptr: *mut Obj) -> Self {
+        Self(NonNull::new_unchecked(ptr))
+    }
+}
+
+impl Vm {
+    /// corresponds to allocateObject. every object is recorded in
+    /// [Vm::objects] so that the garbage collector can find it again. this is
+    /// the only place a collection can start, and `kind` counts as a root
+    /// while it does since the new object isn't reachable from anywhere else
+    /// yet
+    pub(crate) fn allocate_object(&mut self, kind: ObjKind) -> ObjRef {
+        if DEBUG_STRESS_GC || self.bytes_allocated > self.next_gc {
+            self.collect_garbage(Some(&kind));
+        }
+
+        let obj = ObjRef::new(Obj::new(kind));
+        let size = obj.size();
+        self.bytes_allocated += size;
+        self.objects.push(obj);
+
+        if DEBUG_LOG_GC {
+            println!(
+                "{:?} allocate {size} for {}",
+                obj.as_ptr(),
+                obj.type_name()
+            );
+        }
+
+        obj
+    }
+
+    /// corresponds to allocateString. the new string is interned in
+    /// [Vm::strings]
+    fn allocate_string(&mut self, chars: String, hash: u32) -> ObjRef {
+        let string =
+            self.allocate_object(ObjKind::String(ObjString { chars, hash }));
+        self.strings.set(string, Value::nil());
+        string
+    }
+
+    /// return the interned string equal to `chars`, allocating a copy of
+    /// `chars` if there isn't one yet
+    pub(crate) fn copy_string(&mut self, chars: &str) -> ObjRef {
+        let hash = hash_string(chars);
+        if let Some(interned) = self.strings.find_string(chars, hash) {
+            return interned;
+        }
+        self.allocate_string(chars.to_owned(), hash)
+    }
+
+    /// like [Vm::copy_string], but takes ownership of `chars`, which is simply
+    /// dropped if an equal string is already interned
+    pub(crate) fn take_string(&mut self, chars: String) -> ObjRef {
+        let hash = hash_string(&chars);
+        if let Some(interned) = self.strings.find_string(&chars, hash) {
+            return interned;
+        }
+        self.allocate_string(chars, hash)
+    }
+}
@@ -0,0 +1,108 @@
+//! the default representation of [Value]: a plain tagged union
+
+use super::{Obj, ObjRef};
+
+#[derive(Default, Clone, Copy, Debug)]
+pub enum Value {
+    Bool(bool),
+    #[default]
+    Nil,
+    Number(f64),
+    Obj(ObjRef),
+}
+
+impl Value {
+    pub(crate) fn boolean(v: bool) -> Self {
+        Self::Bool(v)
+    }
+
+    pub(crate) fn nil() -> Self {
+        Self::Nil
+    }
+
+    pub(crate) fn number(v: f64) -> Self {
+        Self::Number(v)
+    }
+
+    pub(crate) fn obj(v: ObjRef) -> Self {
+        Self::Obj(v)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        if let Self::Bool(v) = self {
+            Some(*v)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the value is [`Bool`].
+    ///
+    /// [`Bool`]: Value::Bool
+    #[must_use]
+    pub fn is_bool(&self) -> bool {
+        matches!(self, Self::Bool(..))
+    }
+
+    /// Returns `true` if the value is [`Nil`].
+    ///
+    /// [`Nil`]: Value::Nil
+    #[must_use]
+    pub fn is_nil(&self) -> bool {
+        matches!(self, Self::Nil)
+    }
+
+    /// Returns `true` if the value is [`Number`].
+    ///
+    /// [`Number`]: Value::Number
+    #[must_use]
+    pub fn is_number(&self) -> bool {
+        matches!(self, Self::Number(..))
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        if let Self::Number(v) = self {
+            Some(*v)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the value is an [`Obj`].
+    ///
+    /// [`Obj`]: Value::Obj
+    #[must_use]
+    pub fn is_obj(&self) -> bool {
+        matches!(self, Self::Obj(..))
+    }
+
+    pub fn as_obj(&self) -> Option<ObjRef> {
+        if let Self::Obj(v) = self {
+            Some(*v)
+        } else {
+            None
+        }
+    }
+
+    /// borrow the object this value points to, if any
+    pub(crate) fn object(&self) -> Option<&Obj> {
+        if let Self::Obj(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Nil, Value::Nil) => true,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            // strings are interned, so equal strings are the same object
+            (Value::Obj(a), Value::Obj(b)) => a == b,
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,779 @@
+//! a check over a function's bytecode before the vm runs it. [Vm::run]
+//! trusts its chunks completely, which is fine for code straight from the
+//! compiler, but a `.loxc` file could contain anything. the verifier makes sure
+//! every instruction decodes, every operand points at something that exists,
+//! and every path through the code sees the same stack depth at each
+//! instruction, so that the vm can't index out of bounds or underflow the
+//! stack. the depth also has to stay within [FRAME_SLOTS], which is all the vm
+//! makes room for when it starts a call. the types of values on the stack
+//! aren't tracked, so instructions that need a particular type still check
+//! for it when they run
+//!
+//! [Vm::run]: crate::vm::Vm
+
+use std::fmt::Display;
+
+use crate::{
+    chunk::{Chunk, OpCode},
+    value::{ObjFunction, Value},
+    vm::FRAME_SLOTS,
+};
+
+/// what went wrong, and where
+#[derive(Debug, PartialEq)]
+pub struct VerifyError {
+    /// the function containing the bad instruction, like `<fn f>`
+    pub function: String,
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    pub kind: VerifyErrorKind,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum VerifyErrorKind {
+    /// the byte isn't any opcode
+    UnknownOpcode(u8),
+    /// the instruction's operands run past the end of the code
+    Truncated,
+    /// a constant index past the end of the constant table
+    ConstantOutOfRange(usize),
+    /// the constant exists but isn't the kind of value the instruction needs
+    WrongConstantType {
+        index: usize,
+        expected: &'static str,
+    },
+    /// a local slot above the top of the stack
+    LocalOutOfRange(u8),
+    /// an upvalue index past the function's upvalues
+    UpvalueOutOfRange(u8),
+    /// a Closure capture flag other than 0 or 1
+    BadCaptureFlag(u8),
+    /// a jump to a target outside the code or into the middle of another
+    /// instruction
+    BadJumpTarget(isize),
+    /// the instruction pops more values than the function has pushed
+    StackUnderflow,
+    /// the instruction pushes the stack past [FRAME_SLOTS]
+    StackTooDeep,
+    /// two paths reach `target` with different stack depths
+    StackMismatch {
+        target: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// execution can run past the last instruction without returning
+    FallsOffEnd,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[line {}:{}] Invalid bytecode in {} at offset {}: ",
+            self.line, self.column, self.function, self.offset
+        )?;
+        match &self.kind {
+            VerifyErrorKind::UnknownOpcode(op) => {
+                write!(f, "unknown opcode {op}.")
+            }
+            VerifyErrorKind::Truncated => {
+                write!(f, "instruction runs past the end of the code.")
+            }
+            VerifyErrorKind::ConstantOutOfRange(index) => {
+                write!(f, "constant {index} does not exist.")
+            }
+            VerifyErrorKind::WrongConstantType { index, expected } => {
+                write!(f, "constant {index} is not a {expected}.")
+            }
+            VerifyErrorKind::LocalOutOfRange(slot) => {
+                write!(f, "local slot {slot} is above the top of the stack.")
+            }
+            VerifyErrorKind::UpvalueOutOfRange(index) => {
+                write!(f, "upvalue {index} does not exist.")
+            }
+            VerifyErrorKind::BadCaptureFlag(flag) => {
+                write!(f, "capture flag {flag} is not 0 or 1.")
+            }
+            VerifyErrorKind::BadJumpTarget(target) => {
+                write!(f, "jump target {target} is not an instruction.")
+            }
+            VerifyErrorKind::StackUnderflow => write!(f, "stack underflow."),
+            VerifyErrorKind::StackTooDeep => {
+                write!(f, "stack grows past {FRAME_SLOTS} slots.")
+            }
+            VerifyErrorKind::StackMismatch {
+                target,
+                expected,
+                found,
+            } => write!(
+                f,
+                "offset {target} is reached with stack depths {expected} \
+                 and {found}."
+            ),
+            VerifyErrorKind::FallsOffEnd => {
+                write!(f, "execution runs past the end of the code.")
+            }
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// check `function` and every function nested in its constant table
+pub fn verify(function: &ObjFunction) -> Result<(), VerifyError> {
+    verify_function(function)?;
+    let constants = &function.chunk.constants;
+    for i in 0..constants.len() {
+        if let Some(nested) = constants[i].as_function() {
+            verify(nested)?;
+        }
+    }
+    Ok(())
+}
+
+/// check `function` alone, assuming the functions nested in it are fine
+pub(crate) fn verify_function(
+    function: &ObjFunction,
+) -> Result<(), VerifyError> {
+    Verifier::new(function).verify()
+}
+
+/// how an instruction affects the stack and where control goes next
+struct Effect {
+    pops: usize,
+    pushes: usize,
+    /// whether execution can continue with the next instruction
+    falls_through: bool,
+    jump: Option<usize>,
+}
+
+impl Effect {
+    fn new(pops: usize, pushes: usize) -> Self {
+        Self {
+            pops,
+            pushes,
+            falls_through: true,
+            jump: None,
+        }
+    }
+}
+
+struct Verifier<'a> {
+    function: &'a ObjFunction,
+    chunk: &'a Chunk,
+    /// the stack depth on entry to the instruction at each offset, once some
+    /// path has reached it
+    depths: Vec<Option<usize>>,
+    /// whether each offset is the start of an instruction
+    starts: Vec<bool>,
+}
+
+impl<'a> Verifier<'a> {
+    fn new(function: &'a ObjFunction) -> Self {
+        let len = function.chunk.code.len();
+        Self {
+            function,
+            chunk: &function.chunk,
+            depths: vec![None; len],
+            starts: vec![false; len],
+        }
+    }
+
+    fn error(&self, offset: usize, kind: VerifyErrorKind) -> VerifyError {
+        let (line, column) = if self.chunk.lines.is_empty() {
+            (0, 0)
+        } else {
+            let offset = offset.min(self.chunk.code.len().saturating_sub(1));
+            (self.chunk.get_line(offset), self.chunk.get_column(offset))
+        };
+        VerifyError {
+            function: self.function.to_string(),
+            offset,
+            line,
+            column,
+            kind,
+        }
+    }
+
+    fn verify(&mut self) -> Result<(), VerifyError> {
+        // first find where each instruction starts, so that jumps can be
+        // checked against them, then follow every path through the code
+        let mut offset = 0;
+        while offset < self.chunk.code.len() {
+            self.starts[offset] = true;
+            offset += self.length(offset)?;
+        }
+
+        // slot zero holds the function or receiver, followed by the arguments
+        let mut work = vec![(0, self.function.arity + 1)];
+        while let Some((offset, depth)) = work.pop() {
+            if offset == self.chunk.code.len() {
+                return Err(self.error(offset, VerifyErrorKind::FallsOffEnd));
+            }
+            match self.depths[offset] {
+                Some(expected) if expected != depth => {
+                    return Err(self.error(
+                        offset,
+                        VerifyErrorKind::StackMismatch {
+                            target: offset,
+                            expected,
+                            found: depth,
+                        },
+                    ));
+                }
+                Some(_) => continue,
+                None => self.depths[offset] = Some(depth),
+            }
+
+            let effect = self.effect(offset, depth)?;
+            if depth < effect.pops {
+                return Err(self.error(offset, VerifyErrorKind::StackUnderflow));
+            }
+            let depth = depth - effect.pops + effect.pushes;
+            if depth > FRAME_SLOTS {
+                return Err(self.error(offset, VerifyErrorKind::StackTooDeep));
+            }
+            if effect.falls_through {
+                work.push((offset + self.length(offset)?, depth));
+            }
+            if let Some(target) = effect.jump {
+                work.push((target, depth));
+            }
+        }
+        Ok(())
+    }
+
+    fn byte(&self, offset: usize) -> Result<u8, VerifyError> {
+        self.chunk
+            .code
+            .get(offset)
+            .copied()
+            .ok_or_else(|| self.error(offset, VerifyErrorKind::Truncated))
+    }
+
+    /// the operand `n` bytes after the opcode at `offset`. the operand was
+    /// bounds checked by [Verifier::length] before this is called
+    fn operand(&self, offset: usize, n: usize) -> u8 {
+        self.chunk.code[offset + n]
+    }
+
+    fn opcode(&self, offset: usize) -> Result<OpCode, VerifyError> {
+        let byte = self.byte(offset)?;
+        byte.try_into().map_err(|_| {
+            self.error(offset, VerifyErrorKind::UnknownOpcode(byte))
+        })
+    }
+
+    /// the length of the instruction at `offset`, including its operands,
+    /// which are checked to be in bounds
+    fn length(&self, offset: usize) -> Result<usize, VerifyError> {
+        use OpCode::*;
+        let len = match self.opcode(offset)? {
+            Nil | True | False | Pop | Equal | Greater | Less | Add
+            | Subtract | Multiply | Divide | Not | Negate | Print
+            | CloseUpvalue | Return | Inherit => 1,
+            Constant | GetLocal | SetLocal | GetGlobal | DefineGlobal
+            | SetGlobal | GetUpvalue | SetUpvalue | GetProperty
+            | SetProperty | GetSuper | Call | Class | Method => 2,
+            Jump | JumpIfFalse | Loop | Invoke | SuperInvoke => 3,
+            ConstantLong | GetGlobalLong | DefineGlobalLong | SetGlobalLong
+            | GetPropertyLong | SetPropertyLong | GetSuperLong | ClassLong
+            | MethodLong => 4,
+            InvokeLong | SuperInvokeLong => 5,
+            op @ (Closure | ClosureLong) => {
+                let width = if op.is_long() { 3 } else { 1 };
+                self.byte(offset + width)?;
+                let index = self.constant_index(offset, op);
+                let function = self.function_constant(offset, index)?;
+                1 + width + 2 * function.upvalue_count
+            }
+        };
+        self.byte(offset + len - 1)?;
+        Ok(len)
+    }
+
+    fn constant(
+        &self,
+        offset: usize,
+        index: usize,
+    ) -> Result<Value, VerifyError> {
+        if index >= self.chunk.constants.len() {
+            return Err(
+                self.error(offset, VerifyErrorKind::ConstantOutOfRange(index))
+            );
+        }
+        Ok(self.chunk.constants[index])
+    }
+
+    /// the constant index operand of the instruction `op` at `offset`, which
+    /// is three bytes for the `Long` variants and one otherwise
+    fn constant_index(&self, offset: usize, op: OpCode) -> usize {
+        if op.is_long() {
+            let [hi, mid, lo] = [1, 2, 3].map(|n| self.operand(offset, n));
+            u32::from_be_bytes([0, hi, mid, lo]) as usize
+        } else {
+            self.operand(offset, 1) as usize
+        }
+    }
+
+    /// check that the constant operand of the instruction `op` at `offset` is
+    /// the index of a string constant
+    fn string_constant(
+        &self,
+        offset: usize,
+        op: OpCode,
+    ) -> Result<(), VerifyError> {
+        let index = self.constant_index(offset, op);
+        if !self.constant(offset, index)?.is_string() {
+            return Err(self.error(
+                offset,
+                VerifyErrorKind::WrongConstantType {
+                    index,
+                    expected: "string",
+                },
+            ));
+        }
+        Ok(())
+    }
+
+    fn function_constant(
+        &self,
+        offset: usize,
+        index: usize,
+    ) -> Result<&'a ObjFunction, VerifyError> {
+        self.constant(offset, index)?;
+        let chunk: &'a Chunk = self.chunk;
+        chunk.constants[index].as_function().ok_or_else(|| {
+            self.error(
+                offset,
+                VerifyErrorKind::WrongConstantType {
+                    index,
+                    expected: "function",
+                },
+            )
+        })
+    }
+
+    fn local(&self, offset: usize, depth: usize) -> Result<(), VerifyError> {
+        let slot = self.operand(offset, 1);
+        if slot as usize >= depth {
+            return Err(
+                self.error(offset, VerifyErrorKind::LocalOutOfRange(slot))
+            );
+        }
+        Ok(())
+    }
+
+    fn upvalue(&self, offset: usize, index: u8) -> Result<(), VerifyError> {
+        if index as usize >= self.function.upvalue_count {
+            return Err(
+                self.error(offset, VerifyErrorKind::UpvalueOutOfRange(index))
+            );
+        }
+        Ok(())
+    }
+
+    /// the target of the jump at `offset`, which moves `distance` bytes
+    /// forward or back from the end of the instruction
+    fn jump_target(
+        &self,
+        offset: usize,
+        forward: bool,
+    ) -> Result<usize, VerifyError> {
+        let distance = u16::from_be_bytes([
+            self.operand(offset, 1),
+            self.operand(offset, 2),
+        ]) as isize;
+        let next = offset as isize + 3;
+        let target = if forward {
+            next + distance
+        } else {
+            next - distance
+        };
+        match usize::try_from(target) {
+            Ok(t) if self.starts.get(t) == Some(&true) => Ok(t),
+            _ => {
+                Err(self.error(offset, VerifyErrorKind::BadJumpTarget(target)))
+            }
+        }
+    }
+
+    /// check the operands of the instruction at `offset` given the stack
+    /// `depth` before it, and return its effect
+    fn effect(
+        &self,
+        offset: usize,
+        depth: usize,
+    ) -> Result<Effect, VerifyError> {
+        use OpCode::*;
+        let op = self.opcode(offset)?;
+        let effect = match op {
+            Constant | ConstantLong => {
+                self.constant(offset, self.constant_index(offset, op))?;
+                Effect::new(0, 1)
+            }
+            Nil | True | False => Effect::new(0, 1),
+            Pop | Print | CloseUpvalue => Effect::new(1, 0),
+            GetLocal => {
+                self.local(offset, depth)?;
+                Effect::new(0, 1)
+            }
+            SetLocal => {
+                self.local(offset, depth)?;
+                Effect::new(1, 1)
+            }
+            GetGlobal | GetGlobalLong | Class | ClassLong => {
+                self.string_constant(offset, op)?;
+                Effect::new(0, 1)
+            }
+            DefineGlobal | DefineGlobalLong => {
+                self.string_constant(offset, op)?;
+                Effect::new(1, 0)
+            }
+            SetGlobal | SetGlobalLong | GetProperty | GetPropertyLong => {
+                self.string_constant(offset, op)?;
+                Effect::new(1, 1)
+            }
+            GetUpvalue => {
+                self.upvalue(offset, self.operand(offset, 1))?;
+                Effect::new(0, 1)
+            }
+            SetUpvalue => {
+                self.upvalue(offset, self.operand(offset, 1))?;
+                Effect::new(1, 1)
+            }
+            // instance and value, instance and superclass, or class and
+            // method, leaving one
+            SetProperty | SetPropertyLong | GetSuper | GetSuperLong
+            | Method | MethodLong => {
+                self.string_constant(offset, op)?;
+                Effect::new(2, 1)
+            }
+            Equal | Greater | Less | Add | Subtract | Multiply | Divide => {
+                Effect::new(2, 1)
+            }
+            Not | Negate => Effect::new(1, 1),
+            Inherit => Effect::new(2, 1),
+            Jump => Effect {
+                falls_through: false,
+                jump: Some(self.jump_target(offset, true)?),
+                ..Effect::new(0, 0)
+            },
+            JumpIfFalse => Effect {
+                jump: Some(self.jump_target(offset, true)?),
+                // the condition is left on the stack
+                ..Effect::new(1, 1)
+            },
+            Loop => Effect {
+                falls_through: false,
+                jump: Some(self.jump_target(offset, false)?),
+                ..Effect::new(0, 0)
+            },
+            // the callee and arguments are replaced by the result
+            Call => {
+                let arg_count = self.operand(offset, 1) as usize;
+                Effect::new(arg_count + 1, 1)
+            }
+            Invoke | InvokeLong => {
+                self.string_constant(offset, op)?;
+                let arg_count = self.operand(offset, self.length(offset)? - 1);
+                Effect::new(arg_count as usize + 1, 1)
+            }
+            // the superclass is on top of the receiver and arguments
+            SuperInvoke | SuperInvokeLong => {
+                self.string_constant(offset, op)?;
+                let arg_count = self.operand(offset, self.length(offset)? - 1);
+                Effect::new(arg_count as usize + 2, 1)
+            }
+            Closure | ClosureLong => {
+                let index = self.constant_index(offset, op);
+                let function = self.function_constant(offset, index)?;
+                let width = if op.is_long() { 3 } else { 1 };
+                for i in 0..function.upvalue_count {
+                    let is_local = self.operand(offset, 1 + width + 2 * i);
+                    let index = self.operand(offset, 2 + width + 2 * i);
+                    match is_local {
+                        1 if index as usize >= depth => {
+                            return Err(self.error(
+                                offset,
+                                VerifyErrorKind::LocalOutOfRange(index),
+                            ));
+                        }
+                        1 => {}
+                        0 => self.upvalue(offset, index)?,
+                        flag => {
+                            return Err(self.error(
+                                offset,
+                                VerifyErrorKind::BadCaptureFlag(flag),
+                            ));
+                        }
+                    }
+                }
+                Effect::new(0, 1)
+            }
+            Return => Effect {
+                falls_through: false,
+                ..Effect::new(1, 0)
+            },
+        };
+        Ok(effect)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        value::ObjKind,
+        vm::{InterpretError, Vm},
+    };
+    use OpCode::*;
+
+    /// a script function with `code` on line 1 and numbers for constants
+    fn script(code: &[u8], constants: &[f64]) -> ObjFunction {
+        let mut function = ObjFunction::default();
+        for (i, &byte) in code.iter().enumerate() {
+            function.chunk.write_chunk(byte, 1, i + 1);
+        }
+        for &n in constants {
+            function.chunk.add_constant(Value::number(n));
+        }
+        function
+    }
+
+    fn kind(code: &[u8], constants: &[f64]) -> Option<VerifyErrorKind> {
+        verify(&script(code, constants)).err().map(|e| e.kind)
+    }
+
+    #[test]
+    fn compiled_code_verifies() {
+        let mut vm = Vm::new();
+        let source = "
+            class A { init(x) { this.x = x; } get() { return this.x; } }
+            class B < A { get() { return super.get() + 1; } }
+            fun count(n) {
+                var total = 0;
+                for (var i = 0; i < n and true; i = i + 1) {
+                    fun add() { total = total + i; }
+                    add();
+                }
+                while (false or nil) {}
+                return total;
+            }
+            print B(count(3)).get();
+        ";
+        let function = vm.compile(source.to_owned()).unwrap();
+        assert_eq!(verify(function.as_function().unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn valid() {
+        let code = [Constant as u8, 0, Print as u8, Nil as u8, Return as u8];
+        assert_eq!(kind(&code, &[1.0]), None);
+    }
+
+    #[test]
+    fn bad_instructions() {
+        let cases: &[(&[u8], VerifyErrorKind)] = &[
+            (&[250], VerifyErrorKind::UnknownOpcode(250)),
+            (&[Nil as u8, Jump as u8, 0], VerifyErrorKind::Truncated),
+            (
+                &[Constant as u8, 1, Return as u8],
+                VerifyErrorKind::ConstantOutOfRange(1),
+            ),
+            (
+                &[ConstantLong as u8, 1, 0, 0, Return as u8],
+                VerifyErrorKind::ConstantOutOfRange(65536),
+            ),
+            (
+                &[GetGlobal as u8, 0, Return as u8],
+                VerifyErrorKind::WrongConstantType {
+                    index: 0,
+                    expected: "string",
+                },
+            ),
+            (
+                &[GetGlobalLong as u8, 0, 0, 0, Return as u8],
+                VerifyErrorKind::WrongConstantType {
+                    index: 0,
+                    expected: "string",
+                },
+            ),
+            (&[InvokeLong as u8, 0, 0, 0], VerifyErrorKind::Truncated),
+            (&[ClosureLong as u8, 0, 0], VerifyErrorKind::Truncated),
+            (
+                &[ClosureLong as u8, 0, 0, 0, Return as u8],
+                VerifyErrorKind::WrongConstantType {
+                    index: 0,
+                    expected: "function",
+                },
+            ),
+            (
+                &[Closure as u8, 0, Return as u8],
+                VerifyErrorKind::WrongConstantType {
+                    index: 0,
+                    expected: "function",
+                },
+            ),
+            (
+                &[GetLocal as u8, 1, Return as u8],
+                VerifyErrorKind::LocalOutOfRange(1),
+            ),
+            (
+                &[GetUpvalue as u8, 0, Return as u8],
+                VerifyErrorKind::UpvalueOutOfRange(0),
+            ),
+        ];
+        for (code, want) in cases {
+            assert_eq!(kind(code, &[1.0]).as_ref(), Some(want), "{code:?}");
+        }
+    }
+
+    #[test]
+    fn too_deep() {
+        let mut code = vec![Nil as u8; FRAME_SLOTS - 1];
+        code.push(Return as u8);
+        assert_eq!(kind(&code, &[]), None);
+
+        let mut code = vec![Nil as u8; 20_000];
+        code.push(Return as u8);
+        assert_eq!(kind(&code, &[]), Some(VerifyErrorKind::StackTooDeep));
+    }
+
+    /// run `code` with the string "x" as its only constant
+    fn run(vm: &mut Vm, code: &[u8]) -> Result<(), InterpretError> {
+        let mut function = script(code, &[]);
+        let name = vm.copy_string("x");
+        // keep the name alive until the function that refers to it is
+        vm.push(Value::obj(name));
+        function.chunk.add_constant(Value::obj(name));
+        let function = vm.allocate_object(ObjKind::Function(function));
+        vm.pop();
+        vm.run_script(function)
+    }
+
+    #[test]
+    fn wrong_types_are_runtime_errors() {
+        // each of these verifies, since the verifier doesn't track types, but
+        // has something other than a class or method where one belongs
+        let cases: &[&[u8]] = &[
+            &[Nil as u8, Nil as u8, GetSuper as u8, 0, Return as u8],
+            &[Nil as u8, Nil as u8, SuperInvoke as u8, 0, 0, Return as u8],
+            &[Nil as u8, Nil as u8, Method as u8, 0, Return as u8],
+            &[Class as u8, 0, Nil as u8, Method as u8, 0, Return as u8],
+            &[Class as u8, 0, Nil as u8, Inherit as u8, Return as u8],
+        ];
+        let mut vm = Vm::new();
+        for code in cases {
+            assert!(
+                matches!(run(&mut vm, code), Err(InterpretError::RuntimeError)),
+                "{code:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn jumps() {
+        // into the middle of the Constant instruction
+        let code = [Jump as u8, 0, 1, Constant as u8, 0, Return as u8];
+        assert_eq!(
+            kind(&code, &[1.0]),
+            Some(VerifyErrorKind::BadJumpTarget(4))
+        );
+
+        // before the start of the code
+        let code = [Loop as u8, 0, 4, Return as u8];
+        assert_eq!(kind(&code, &[]), Some(VerifyErrorKind::BadJumpTarget(-1)));
+
+        // a loop back to the start is fine as long as the stack is balanced
+        let code = [Nil as u8, Pop as u8, Loop as u8, 0, 5];
+        assert_eq!(kind(&code, &[]), None);
+        let code = [Nil as u8, Loop as u8, 0, 4];
+        assert_eq!(
+            kind(&code, &[]),
+            Some(VerifyErrorKind::StackMismatch {
+                target: 0,
+                expected: 1,
+                found: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn stack_depth() {
+        let code = [Pop as u8, Pop as u8, Nil as u8, Return as u8];
+        assert_eq!(kind(&code, &[]), Some(VerifyErrorKind::StackUnderflow));
+
+        // the two arms of an if leave different amounts on the stack
+        let code =
+            [True as u8, JumpIfFalse as u8, 0, 1, Nil as u8, Return as u8];
+        assert_eq!(
+            kind(&code, &[]),
+            Some(VerifyErrorKind::StackMismatch {
+                target: 5,
+                expected: 2,
+                found: 3,
+            })
+        );
+
+        let code = [Nil as u8, Pop as u8];
+        assert_eq!(kind(&code, &[]), Some(VerifyErrorKind::FallsOffEnd));
+        assert_eq!(kind(&[], &[]), Some(VerifyErrorKind::FallsOffEnd));
+    }
+
+    #[test]
+    fn reports_position() {
+        let error = verify(&script(&[Nil as u8, 250], &[])).unwrap_err();
+        assert_eq!(error.offset, 1);
+        assert_eq!((error.line, error.column), (1, 2));
+        assert_eq!(error.function, "<script>");
+    }
+
+    #[test]
+    fn bad_bytecode_does_not_run() {
+        let mut vm = Vm::new();
+        let mut bytes = vm.compile_to_bytes("print 1;".to_owned()).unwrap();
+        // the script's code follows the magic, version, name flag, arity,
+        // upvalue count, and code length
+        let code = crate::bytecode::MAGIC.len() + 4 + 1 + 4 + 4 + 4;
+        assert_eq!(bytes[code], Constant as u8);
+        bytes[code] = 250;
+        assert!(vm.interpret_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn nan_payloads_do_not_run() {
+        // loaded constants are only checked for their kind of value when
+        // they're used, so a NaN whose payload looks like an object pointer
+        // has to become a plain number before it gets that far
+        let mut vm = Vm::new();
+        let mut bytes = vm
+            .compile_to_bytes("print 1.5 + \"a\";".to_owned())
+            .unwrap();
+        let at = bytes
+            .windows(8)
+            .position(|w| w == 1.5f64.to_le_bytes())
+            .unwrap();
+        bytes[at..at + 8].copy_from_slice(&0xfffc000000001000u64.to_le_bytes());
+        assert!(matches!(
+            vm.interpret_bytes(&bytes),
+            Err(InterpretError::RuntimeError)
+        ));
+    }
+
+    #[test]
+    fn deep_expressions_are_compile_errors() {
+        // each level holds its left operand while the right one is compiled
+        let mut vm = Vm::new();
+        let depth = FRAME_SLOTS + 100;
+        let source =
+            format!("print {}1{};", "(1 + ".repeat(depth), ")".repeat(depth));
+        assert!(vm.compile(source).is_err());
+
+        let depth = FRAME_SLOTS - 100;
+        let source =
+            format!("print {}1{};", "(1 + ".repeat(depth), ")".repeat(depth));
+        assert!(vm.compile(source).is_ok());
+    }
+}
@@ -2,22 +2,94 @@
 //! version, it does not define a global singleton and instead defines the
 //! functions that manipulate the Vm as methods on a [Vm] instance
 
+use std::{
+    cell::{Cell, RefCell},
+    time::SystemTime,
+};
+
 use crate::{
     chunk::{Chunk, OpCode},
-    compile::Parser,
-    value::Value,
+    compile::{ClassCompiler, Compiler, Parser},
+    table::Table,
+    value::{
+        NativeFn, ObjBoundMethod, ObjClass, ObjClosure, ObjFunction,
+        ObjInstance, ObjKind, ObjNative, ObjRef, ObjUpvalue, Value,
+    },
+    verify::verify,
     DEBUG_TRACE_EXECUTION,
 };
 
-const STACK_MAX: usize = 256;
+const FRAMES_MAX: usize = 64;
+const STACK_MAX: usize = FRAMES_MAX * (u8::MAX as usize + 1);
+
+/// the most stack slots one call may use, counting the callee, its arguments
+/// and locals, and any temporaries. a call is only started if this many slots
+/// are free above its base, which is enough room for the likes of a function
+/// with 255 parameters passing all of them to another call. the compiler
+/// reports functions that could use more as errors, and the verifier rejects
+/// any that are loaded
+pub(crate) const FRAME_SLOTS: usize = 4 * (u8::MAX as usize + 1);
+
+/// an ongoing function call
+pub(crate) struct CallFrame {
+    pub(crate) closure: ObjRef,
+    /// index of the next instruction in the function's chunk. unlike the C
+    /// version, this is kept in the frame rather than cached in a local
+    ip: usize,
+    /// index of the first stack slot the function can use
+    slots: usize,
+}
+
+impl CallFrame {
+    fn closure(&self) -> &ObjClosure {
+        self.closure.as_closure().unwrap()
+    }
+
+    fn function(&self) -> &ObjFunction {
+        self.closure().function.as_function().unwrap()
+    }
+}
+
+fn clock_native(_: &[Value]) -> Value {
+    Value::number(
+        SystemTime::UNIX_EPOCH.elapsed().unwrap().as_millis() as f64 / 1000.0,
+    )
+}
 
 /// use usizes instead of pointers to elements
 pub struct Vm {
-    pub(crate) chunk: Option<Chunk>,
-    ip: usize,
-    stack: [Value; STACK_MAX],
-    stack_top: usize,
+    pub(crate) frames: Vec<CallFrame>,
+    pub(crate) stack: [Value; STACK_MAX],
+    pub(crate) stack_top: usize,
     pub(crate) parser: Parser,
+    pub(crate) compiler: Compiler,
+    pub(crate) class_compiler: Option<Box<ClassCompiler>>,
+
+    /// every object allocated by the vm, corresponding to the intrusive linked
+    /// list `vm.objects` in the C version
+    pub(crate) objects: Vec<ObjRef>,
+
+    /// the set of interned strings. only the keys are used
+    pub(crate) strings: Table,
+
+    pub(crate) globals: Table,
+
+    /// the interned string "init", for looking up initializers quickly.
+    /// `None` only while the vm is being constructed
+    pub(crate) init_string: Option<ObjRef>,
+
+    /// upvalues still pointing at live stack slots, sorted by slot. the C
+    /// version threads these into a linked list through the upvalues
+    /// themselves
+    pub(crate) open_upvalues: Vec<ObjRef>,
+
+    /// total size of live objects as estimated by
+    /// [Obj::size](crate::value::Obj::size)
+    pub(crate) bytes_allocated: usize,
+    /// the value of `bytes_allocated` that will trigger the next collection
+    pub(crate) next_gc: usize,
+    /// objects marked reachable whose references haven't been traced yet
+    pub(crate) gray_stack: Vec<ObjRef>,
 }
 
 #[derive(Debug)]
@@ -42,43 +114,98 @@ macro_rules! binary_op {
 
 impl Vm {
     pub fn new() -> Self {
-        Self {
-            chunk: None,
-            ip: 0,
+        let mut vm = Self {
+            frames: Vec::with_capacity(FRAMES_MAX),
             // this would actually be a prime use for maybeuninit or
             // mem::uninitialized
             stack: [Value::default(); STACK_MAX],
             stack_top: 0,
             parser: Parser::default(),
-        }
+            compiler: Compiler::default(),
+            class_compiler: None,
+            objects: Vec::new(),
+            strings: Table::new(),
+            globals: Table::new(),
+            init_string: None,
+            open_upvalues: Vec::new(),
+            bytes_allocated: 0,
+            next_gc: 1024 * 1024,
+            gray_stack: Vec::new(),
+        };
+        vm.init_string = Some(vm.copy_string("init"));
+        vm.define_native("clock", clock_native);
+        vm
     }
 
     fn reset_stack(&mut self) {
         self.stack_top = 0;
+        self.frames.clear();
+        self.open_upvalues.clear();
     }
 
+    /// report `msg` along with a trace of the call stack
     fn runtime_error(&mut self, msg: &str) {
         eprintln!("{msg}");
 
-        let instruction = self.ip - 1;
-        let line = self.chunk.as_ref().unwrap().lines[instruction];
-        eprintln!("[line {line} in script\n");
+        for frame in self.frames.iter().rev() {
+            let function = frame.function();
+            let instruction = frame.ip - 1;
+            let line = function.chunk.get_line(instruction);
+            match function.name {
+                Some(name) => eprintln!("[line {line}] in {}()", *name),
+                None => eprintln!("[line {line}] in script"),
+            }
+        }
+
         self.reset_stack();
     }
 
+    fn define_native(&mut self, name: &str, function: NativeFn) {
+        // both objects are kept on the stack while allocating so that they're
+        // reachable by the garbage collector
+        let name = self.copy_string(name);
+        self.push(Value::obj(name));
+        let native =
+            self.allocate_object(ObjKind::Native(ObjNative { function }));
+        self.push(Value::obj(native));
+        self.globals.set(name, self.stack[1]);
+        self.pop();
+        self.pop();
+    }
+
     pub fn interpret(&mut self, source: String) -> Result<(), InterpretError> {
-        let chunk = self.compile(source)?;
-        self.chunk = Some(chunk);
-        self.ip = 0;
+        let function = self.compile(source)?;
+        self.run_script(function)
+    }
+
+    /// verify the top-level `function`, then wrap it in a closure and run it.
+    /// a chunk that fails verification is reported like a compile error
+    pub(crate) fn run_script(
+        &mut self,
+        function: ObjRef,
+    ) -> Result<(), InterpretError> {
+        if let Err(e) = verify(function.as_function().unwrap()) {
+            eprintln!("{e}");
+            return Err(InterpretError::CompileError);
+        }
+
+        self.push(Value::obj(function));
+        let closure = self.allocate_object(ObjKind::Closure(ObjClosure {
+            function,
+            upvalues: Vec::new(),
+        }));
+        self.pop();
+        self.push(Value::obj(closure));
+        self.call_closure(closure, 0)?;
         self.run()
     }
 
-    fn push(&mut self, value: Value) {
+    pub(crate) fn push(&mut self, value: Value) {
         self.stack[self.stack_top] = value;
         self.stack_top += 1;
     }
 
-    fn pop(&mut self) -> Value {
+    pub(crate) fn pop(&mut self) -> Value {
         self.stack_top -= 1;
         std::mem::take(&mut self.stack[self.stack_top])
     }
@@ -88,15 +215,271 @@ impl Vm {
         &self.stack[self.stack_top - 1 - distance]
     }
 
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().unwrap()
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().unwrap()
+    }
+
     fn read_byte(&mut self) -> u8 {
-        let res = self.chunk.as_mut().unwrap().code[self.ip];
-        self.ip += 1;
-        res
+        let frame = self.frame_mut();
+        let ip = frame.ip;
+        frame.ip += 1;
+        self.frame().function().chunk.code[ip]
+    }
+
+    /// read a two-byte, big-endian operand
+    fn read_short(&mut self) -> u16 {
+        let hi = self.read_byte();
+        let lo = self.read_byte();
+        u16::from_be_bytes([hi, lo])
+    }
+
+    /// read a constant whose index is a one-byte operand, or a three-byte,
+    /// big-endian one if `long`
+    pub(crate) fn read_constant(&mut self, long: bool) -> Value {
+        let index = if long {
+            let hi = self.read_byte();
+            let mid = self.read_byte();
+            let lo = self.read_byte();
+            u32::from_be_bytes([0, hi, mid, lo]) as usize
+        } else {
+            self.read_byte() as usize
+        };
+        self.frame().function().chunk.constants[index]
+    }
+
+    /// read a constant that the compiler guarantees to be a string, such as a
+    /// variable name
+    fn read_string(&mut self, long: bool) -> ObjRef {
+        self.read_constant(long).as_obj().unwrap()
+    }
+
+    /// push a new frame for calling `closure`, whose arguments are already on
+    /// the stack
+    fn call_closure(
+        &mut self,
+        closure: ObjRef,
+        arg_count: usize,
+    ) -> Result<(), InterpretError> {
+        let function = closure.as_closure().unwrap().function;
+        let arity = function.as_function().unwrap().arity;
+        if arg_count != arity {
+            self.runtime_error(&format!(
+                "Expected {arity} arguments but got {arg_count}."
+            ));
+            return Err(InterpretError::RuntimeError);
+        }
+
+        let slots = self.stack_top - arg_count - 1;
+        if self.frames.len() == FRAMES_MAX || slots + FRAME_SLOTS > STACK_MAX {
+            self.runtime_error("Stack overflow.");
+            return Err(InterpretError::RuntimeError);
+        }
+
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            slots,
+        });
+        Ok(())
+    }
+
+    fn call_value(
+        &mut self,
+        callee: Value,
+        arg_count: usize,
+    ) -> Result<(), InterpretError> {
+        if let Some(obj) = callee.as_obj() {
+            match &obj.kind {
+                ObjKind::BoundMethod(bound) => {
+                    // put the receiver in slot zero, where the method expects
+                    // to find `this`
+                    self.stack[self.stack_top - arg_count - 1] = bound.receiver;
+                    return self.call_closure(bound.method, arg_count);
+                }
+                ObjKind::Class(class) => {
+                    let instance =
+                        self.allocate_object(ObjKind::Instance(ObjInstance {
+                            class: obj,
+                            fields: RefCell::new(Table::new()),
+                        }));
+                    self.stack[self.stack_top - arg_count - 1] =
+                        Value::obj(instance);
+                    let init = self.init_string.unwrap();
+                    let initializer = class.methods.borrow().get(init);
+                    if let Some(initializer) = initializer {
+                        return self.call_closure(
+                            initializer.as_obj().unwrap(),
+                            arg_count,
+                        );
+                    } else if arg_count != 0 {
+                        self.runtime_error(&format!(
+                            "Expected 0 arguments but got {arg_count}."
+                        ));
+                        return Err(InterpretError::RuntimeError);
+                    }
+                    return Ok(());
+                }
+                ObjKind::Closure(_) => {
+                    return self.call_closure(obj, arg_count)
+                }
+                ObjKind::Native(native) => {
+                    let args = &self.stack[self.stack_top - arg_count..];
+                    let result = (native.function)(&args[..arg_count]);
+                    self.stack_top -= arg_count + 1;
+                    self.push(result);
+                    return Ok(());
+                }
+                ObjKind::Function(_)
+                | ObjKind::Instance(_)
+                | ObjKind::String(_)
+                | ObjKind::Upvalue(_) => {}
+            }
+        }
+        self.runtime_error("Can only call functions and classes.");
+        Err(InterpretError::RuntimeError)
+    }
+
+    /// call the method `name` of `class` with the receiver and arguments
+    /// already on the stack
+    fn invoke_from_class(
+        &mut self,
+        class: ObjRef,
+        name: ObjRef,
+        arg_count: usize,
+    ) -> Result<(), InterpretError> {
+        let method = class.as_class().unwrap().methods.borrow().get(name);
+        let Some(method) = method else {
+            self.runtime_error(&format!("Undefined property '{}'.", *name));
+            return Err(InterpretError::RuntimeError);
+        };
+        self.call_closure(method.as_obj().unwrap(), arg_count)
+    }
+
+    /// the combination of GetProperty and Call, without creating a bound
+    /// method in between
+    fn invoke(
+        &mut self,
+        name: ObjRef,
+        arg_count: usize,
+    ) -> Result<(), InterpretError> {
+        let receiver = *self.peek(arg_count);
+        let Some(instance) = receiver.as_instance() else {
+            self.runtime_error("Only instances have methods.");
+            return Err(InterpretError::RuntimeError);
+        };
+
+        // a field holding a function shadows any method of the same name
+        let field = instance.fields.borrow().get(name);
+        if let Some(value) = field {
+            self.stack[self.stack_top - arg_count - 1] = value;
+            return self.call_value(value, arg_count);
+        }
+
+        self.invoke_from_class(instance.class, name, arg_count)
+    }
+
+    /// replace the instance on top of the stack with its method `name` bound
+    /// to it. returns false if there is no such method
+    fn bind_method(&mut self, class: ObjRef, name: ObjRef) -> bool {
+        let method = class.as_class().unwrap().methods.borrow().get(name);
+        let Some(method) = method else {
+            return false;
+        };
+
+        let bound =
+            self.allocate_object(ObjKind::BoundMethod(ObjBoundMethod {
+                receiver: *self.peek(0),
+                method: method.as_obj().unwrap(),
+            }));
+        self.pop();
+        self.push(Value::obj(bound));
+        true
+    }
+
+    /// add the closure on top of the stack to the class just below it
+    fn define_method(&mut self, name: ObjRef) -> Result<(), InterpretError> {
+        let class =
+            self.peek_class(1, "Can only define methods on classes.")?;
+        let method = *self.peek(0);
+        if method.object().and_then(|o| o.as_closure()).is_none() {
+            self.runtime_error("Method must be a function.");
+            return Err(InterpretError::RuntimeError);
+        }
+        class
+            .as_class()
+            .unwrap()
+            .methods
+            .borrow_mut()
+            .set(name, method);
+        self.pop();
+        Ok(())
     }
 
-    pub(crate) fn read_constant(&mut self) -> Value {
-        let b = self.read_byte();
-        self.chunk.as_mut().unwrap().constants[b as usize]
+    /// the class at `distance` from the top of the stack. the compiler always
+    /// puts one there for the instructions that call this, but a loaded chunk
+    /// can put anything there, so it's checked rather than unwrapped
+    fn peek_class(
+        &mut self,
+        distance: usize,
+        message: &str,
+    ) -> Result<ObjRef, InterpretError> {
+        match self.peek(distance).as_obj() {
+            Some(obj) if obj.as_class().is_some() => Ok(obj),
+            _ => {
+                self.runtime_error(message);
+                Err(InterpretError::RuntimeError)
+            }
+        }
+    }
+
+    /// return an upvalue for the variable in stack slot `local`, reusing an
+    /// existing one if another closure already captured it
+    fn capture_upvalue(&mut self, local: usize) -> ObjRef {
+        let index = self
+            .open_upvalues
+            .partition_point(|u| u.as_upvalue().unwrap().location < local);
+        if let Some(&upvalue) = self.open_upvalues.get(index) {
+            if upvalue.as_upvalue().unwrap().location == local {
+                return upvalue;
+            }
+        }
+
+        let created = self.allocate_object(ObjKind::Upvalue(ObjUpvalue {
+            location: local,
+            closed: Cell::new(None),
+        }));
+        self.open_upvalues.insert(index, created);
+        created
+    }
+
+    /// close every open upvalue for a stack slot at or above `last`, moving
+    /// the variable off of the stack and into the upvalue
+    fn close_upvalues(&mut self, last: usize) {
+        while let Some(&upvalue) = self.open_upvalues.last() {
+            let upvalue = upvalue.as_upvalue().unwrap();
+            if upvalue.location < last {
+                break;
+            }
+            upvalue.closed.set(Some(self.stack[upvalue.location]));
+            self.open_upvalues.pop();
+        }
+    }
+
+    fn upvalue(&self, slot: usize) -> &ObjUpvalue {
+        self.frame().closure().upvalues[slot].as_upvalue().unwrap()
+    }
+
+    fn concatenate(&mut self) {
+        let b = self.pop();
+        let a = self.pop();
+        let b = &b.as_string().unwrap().chars;
+        let a = &a.as_string().unwrap().chars;
+        let result = self.take_string(format!("{a}{b}"));
+        self.push(Value::obj(result));
     }
 
     fn run(&mut self) -> Result<(), InterpretError> {
@@ -107,42 +490,157 @@ impl Vm {
                     print!("[ {slot} ]");
                 }
                 println!();
-                self.chunk
-                    .as_mut()
-                    .unwrap()
-                    .disassemble_instruction(self.ip);
+                let frame = self.frame();
+                frame.function().chunk.disassemble_instruction(frame.ip);
             }
             let instruction = self.read_byte();
             match instruction.try_into() {
-                Ok(OpCode::Constant) => {
-                    let constant = self.read_constant();
+                Ok(op @ (OpCode::Constant | OpCode::ConstantLong)) => {
+                    let constant = self.read_constant(op.is_long());
                     self.push(constant);
                 }
                 Ok(OpCode::Nil) => self.push(Value::nil()),
                 Ok(OpCode::True) => self.push(Value::boolean(true)),
                 Ok(OpCode::False) => self.push(Value::boolean(false)),
+                Ok(OpCode::Pop) => {
+                    self.pop();
+                }
+                Ok(OpCode::GetLocal) => {
+                    let slot = self.read_byte() as usize;
+                    self.push(self.stack[self.frame().slots + slot]);
+                }
+                Ok(OpCode::SetLocal) => {
+                    let slot = self.read_byte() as usize;
+                    self.stack[self.frame().slots + slot] = *self.peek(0);
+                }
+                Ok(op @ (OpCode::GetGlobal | OpCode::GetGlobalLong)) => {
+                    let name = self.read_string(op.is_long());
+                    let Some(value) = self.globals.get(name) else {
+                        self.runtime_error(&format!(
+                            "Undefined variable '{}'.",
+                            *name
+                        ));
+                        return Err(InterpretError::RuntimeError);
+                    };
+                    self.push(value);
+                }
+                Ok(op @ (OpCode::DefineGlobal | OpCode::DefineGlobalLong)) => {
+                    let name = self.read_string(op.is_long());
+                    let value = *self.peek(0);
+                    self.globals.set(name, value);
+                    self.pop();
+                }
+                Ok(op @ (OpCode::SetGlobal | OpCode::SetGlobalLong)) => {
+                    let name = self.read_string(op.is_long());
+                    let value = *self.peek(0);
+                    if self.globals.set(name, value) {
+                        self.globals.delete(name);
+                        self.runtime_error(&format!(
+                            "Undefined variable '{}'.",
+                            *name
+                        ));
+                        return Err(InterpretError::RuntimeError);
+                    }
+                }
+                Ok(OpCode::GetUpvalue) => {
+                    let slot = self.read_byte() as usize;
+                    let upvalue = self.upvalue(slot);
+                    let value = upvalue
+                        .closed
+                        .get()
+                        .unwrap_or(self.stack[upvalue.location]);
+                    self.push(value);
+                }
+                Ok(OpCode::SetUpvalue) => {
+                    let slot = self.read_byte() as usize;
+                    let value = *self.peek(0);
+                    let upvalue = self.upvalue(slot);
+                    if upvalue.closed.get().is_some() {
+                        upvalue.closed.set(Some(value));
+                    } else {
+                        let location = upvalue.location;
+                        self.stack[location] = value;
+                    }
+                }
+                Ok(op @ (OpCode::GetProperty | OpCode::GetPropertyLong)) => {
+                    let receiver = *self.peek(0);
+                    let Some(instance) = receiver.as_instance() else {
+                        self.runtime_error("Only instances have properties.");
+                        return Err(InterpretError::RuntimeError);
+                    };
+                    let class = instance.class;
+                    let name = self.read_string(op.is_long());
+
+                    let field = instance.fields.borrow().get(name);
+                    if let Some(value) = field {
+                        self.pop(); // instance
+                        self.push(value);
+                    } else if !self.bind_method(class, name) {
+                        self.runtime_error(&format!(
+                            "Undefined property '{}'.",
+                            *name
+                        ));
+                        return Err(InterpretError::RuntimeError);
+                    }
+                }
+                Ok(op @ (OpCode::SetProperty | OpCode::SetPropertyLong)) => {
+                    let receiver = *self.peek(1);
+                    let Some(instance) = receiver.as_instance() else {
+                        self.runtime_error("Only instances have fields.");
+                        return Err(InterpretError::RuntimeError);
+                    };
+                    let name = self.read_string(op.is_long());
+                    instance.fields.borrow_mut().set(name, *self.peek(0));
+                    let value = self.pop();
+                    self.pop(); // instance
+                    self.push(value);
+                }
+                Ok(op @ (OpCode::GetSuper | OpCode::GetSuperLong)) => {
+                    let name = self.read_string(op.is_long());
+                    let superclass =
+                        self.peek_class(0, "Superclass must be a class.")?;
+                    self.pop();
+                    if !self.bind_method(superclass, name) {
+                        self.runtime_error(&format!(
+                            "Undefined property '{}'.",
+                            *name
+                        ));
+                        return Err(InterpretError::RuntimeError);
+                    }
+                }
                 Ok(OpCode::Equal) => {
                     let b = self.pop();
                     let a = self.pop();
                     self.push(Value::boolean(a.eq(&b)));
                 }
                 Ok(OpCode::Greater) => {
-                    binary_op!(self, >, Bool);
+                    binary_op!(self, >, boolean);
                 }
                 Ok(OpCode::Less) => {
-                    binary_op!(self, <, Bool);
+                    binary_op!(self, <, boolean);
                 }
                 Ok(OpCode::Add) => {
-                    binary_op!(self, +, Number);
+                    if self.peek(0).is_string() && self.peek(1).is_string() {
+                        self.concatenate();
+                    } else if self.peek(0).is_number()
+                        && self.peek(1).is_number()
+                    {
+                        binary_op!(self, +, number);
+                    } else {
+                        self.runtime_error(
+                            "Operands must be two numbers or two strings.",
+                        );
+                        return Err(InterpretError::RuntimeError);
+                    }
                 }
                 Ok(OpCode::Subtract) => {
-                    binary_op!(self, -, Number);
+                    binary_op!(self, -, number);
                 }
                 Ok(OpCode::Multiply) => {
-                    binary_op!(self, *, Number);
+                    binary_op!(self, *, number);
                 }
                 Ok(OpCode::Divide) => {
-                    binary_op!(self, /, Number);
+                    binary_op!(self, /, number);
                 }
                 Ok(OpCode::Not) => {
                     let tmp = self.pop();
@@ -157,18 +655,191 @@ impl Vm {
                     let tmp = tmp.as_number().unwrap();
                     self.push(Value::number(-tmp));
                 }
-                Ok(OpCode::Return) => {
+                Ok(OpCode::Print) => {
                     println!("{}", self.pop());
-                    return Ok(());
                 }
-                Err(_) => todo!(),
+                Ok(OpCode::Jump) => {
+                    let offset = self.read_short();
+                    self.frame_mut().ip += offset as usize;
+                }
+                Ok(OpCode::JumpIfFalse) => {
+                    let offset = self.read_short();
+                    if self.peek(0).is_falsey() {
+                        self.frame_mut().ip += offset as usize;
+                    }
+                }
+                Ok(OpCode::Loop) => {
+                    let offset = self.read_short();
+                    self.frame_mut().ip -= offset as usize;
+                }
+                Ok(OpCode::Call) => {
+                    let arg_count = self.read_byte() as usize;
+                    self.call_value(*self.peek(arg_count), arg_count)?;
+                }
+                Ok(op @ (OpCode::Invoke | OpCode::InvokeLong)) => {
+                    let method = self.read_string(op.is_long());
+                    let arg_count = self.read_byte() as usize;
+                    self.invoke(method, arg_count)?;
+                }
+                Ok(op @ (OpCode::SuperInvoke | OpCode::SuperInvokeLong)) => {
+                    let method = self.read_string(op.is_long());
+                    let arg_count = self.read_byte() as usize;
+                    let superclass =
+                        self.peek_class(0, "Superclass must be a class.")?;
+                    self.pop();
+                    self.invoke_from_class(superclass, method, arg_count)?;
+                }
+                Ok(op @ (OpCode::Closure | OpCode::ClosureLong)) => {
+                    let function =
+                        self.read_constant(op.is_long()).as_obj().unwrap();
+                    let upvalue_count =
+                        function.as_function().unwrap().upvalue_count;
+                    let mut upvalues = Vec::with_capacity(upvalue_count);
+                    for _ in 0..upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte() as usize;
+                        if is_local {
+                            let local = self.frame().slots + index;
+                            upvalues.push(self.capture_upvalue(local));
+                        } else {
+                            upvalues
+                                .push(self.frame().closure().upvalues[index]);
+                        }
+                    }
+                    let closure =
+                        self.allocate_object(ObjKind::Closure(ObjClosure {
+                            function,
+                            upvalues,
+                        }));
+                    self.push(Value::obj(closure));
+                }
+                Ok(OpCode::CloseUpvalue) => {
+                    self.close_upvalues(self.stack_top - 1);
+                    self.pop();
+                }
+                Ok(OpCode::Return) => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().unwrap();
+                    self.close_upvalues(frame.slots);
+                    if self.frames.is_empty() {
+                        // pop the script function itself and exit the
+                        // interpreter
+                        self.pop();
+                        return Ok(());
+                    }
+
+                    self.stack_top = frame.slots;
+                    self.push(result);
+                }
+                Ok(op @ (OpCode::Class | OpCode::ClassLong)) => {
+                    let name = self.read_string(op.is_long());
+                    let class =
+                        self.allocate_object(ObjKind::Class(ObjClass {
+                            name,
+                            methods: RefCell::new(Table::new()),
+                        }));
+                    self.push(Value::obj(class));
+                }
+                Ok(OpCode::Inherit) => {
+                    let superclass = *self.peek(1);
+                    let Some(superclass) = superclass.as_class() else {
+                        self.runtime_error("Superclass must be a class.");
+                        return Err(InterpretError::RuntimeError);
+                    };
+                    // copy the inherited methods down into the subclass.
+                    // methods defined by the subclass come later and
+                    // overwrite them
+                    let subclass =
+                        self.peek_class(0, "Subclass must be a class.")?;
+                    superclass.methods.borrow().add_all(
+                        &mut subclass.as_class().unwrap().methods.borrow_mut(),
+                    );
+                    self.pop(); // subclass
+                }
+                Ok(op @ (OpCode::Method | OpCode::MethodLong)) => {
+                    let name = self.read_string(op.is_long());
+                    self.define_method(name)?;
+                }
+                Err(()) => unreachable!(
+                    "unknown opcode {instruction}, but chunks are verified \
+                     before they run"
+                ),
             }
         }
     }
 }
 
+impl Drop for Vm {
+    fn drop(&mut self) {
+        self.free_objects();
+    }
+}
+
 impl Default for Vm {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stack_overflow() {
+        let mut vm = Vm::new();
+        let result = vm.interpret("fun f() { f(); } f();".to_owned());
+        assert!(matches!(result, Err(InterpretError::RuntimeError)));
+
+        // a frame with a full set of arguments runs out of slots before it
+        // runs out of frames
+        let params = (0..255)
+            .map(|i| format!("a{i}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let args = vec!["nil"; 255].join(", ");
+        let source = format!("fun g({params}) {{ g({params}); }} g({args});");
+        let result = vm.interpret(source);
+        assert!(matches!(result, Err(InterpretError::RuntimeError)));
+    }
+
+    /// the value of the global `name`, if it's defined
+    fn global(vm: &mut Vm, name: &str) -> Option<Value> {
+        let name = vm.copy_string(name);
+        vm.globals.get(name)
+    }
+
+    #[test]
+    fn many_constants() {
+        // more numbers than a one-byte operand can index, so that everything
+        // after them in the same chunk needs the long instructions
+        let fill: String = (0..300).map(|i| format!("{i}.5; ")).collect();
+        let source = format!(
+            "{fill}
+            var late = 1;
+            late = late + 1;
+            class A {{
+                init(x) {{ this.x = x; }}
+                get() {{ return this.x; }}
+            }}
+            class B < A {{
+                get() {{ {fill} return super.get() + 1; }}
+                bound() {{ {fill} return super.get; }}
+            }}
+            fun f(b) {{ return b.get() + b.bound()(); }}
+            var b = B(late);
+            b.y = f(b);
+            var result = b.y + b.get();"
+        );
+
+        let mut vm = Vm::new();
+        vm.interpret(source.clone()).unwrap();
+        assert_eq!(global(&mut vm, "result"), Some(Value::number(8.0)));
+
+        // the long instructions also survive a trip through a .loxc file
+        let bytes = vm.compile_to_bytes(source).unwrap();
+        let mut vm = Vm::new();
+        vm.interpret_bytes(&bytes).unwrap();
+        assert_eq!(global(&mut vm, "result"), Some(Value::number(8.0)));
+    }
+}
@@ -1,73 +1,51 @@
-use std::{
-    cell::RefCell,
-    collections::HashMap,
-    ops::{Index, IndexMut},
-    rc::Rc,
-};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use crate::{
     interpreter::{value::Value, RuntimeError},
     token::Token,
 };
 
-type StackVal = HashMap<String, Rc<RefCell<Value>>>;
-
-/// NOTE instead of representing an Environment as a HashMap with an optional
-/// enclosing HashMap, which led to disastrous lifetime issues, we model the
-/// environment as a stack of HashMaps with a pointer (index) to the current
-/// entry. Traversing the list of parents becomes decrementing current and
-/// recursing
-#[derive(Clone, Debug, PartialEq)]
+/// NOTE this is the book's representation: a map of values with a pointer to
+/// the enclosing scope. an earlier version modeled the environment as a stack
+/// of HashMaps to avoid lifetime issues, but then closures had to copy the
+/// whole stack and could never see later changes to the scopes they captured.
+/// sharing each scope through an `Rc<RefCell<_>>` lets a closure hold onto its
+/// defining scope for as long as it needs it
+#[derive(Default)]
 pub(crate) struct Environment {
-    pub(crate) stack: Vec<StackVal>,
-}
-
-impl Index<usize> for Environment {
-    type Output = StackVal;
-
-    fn index(&self, index: usize) -> &Self::Output {
-        &self.stack[index]
-    }
-}
-
-impl IndexMut<usize> for Environment {
-    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        &mut self.stack[index]
-    }
+    enclosing: Option<Rc<RefCell<Environment>>>,
+    values: HashMap<String, Rc<RefCell<Value>>>,
 }
 
 type EnvResult = Result<Rc<RefCell<Value>>, RuntimeError>;
 
 impl Environment {
+    /// construct the outermost (global) environment
     pub(crate) fn new() -> Self {
         Self {
-            stack: vec![HashMap::new()],
+            enclosing: None,
+            values: HashMap::new(),
         }
     }
 
-    /// add a new frame to self and adjust the stack pointer to point to it
-    pub(crate) fn push(&mut self) {
-        self.stack.push(HashMap::new());
-    }
-
-    /// pop a stack frame from self and adjust the stack pointer to point to the
-    /// end. panics if the stack is empty
-    pub(crate) fn pop(&mut self) {
-        self.stack.pop();
+    /// construct a new scope nested inside of `enclosing`
+    pub(crate) fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            enclosing: Some(enclosing),
+            values: HashMap::new(),
+        }
     }
 
     pub(crate) fn define(&mut self, name: String, value: Value) {
-        let i = self.stack.len() - 1;
-        self.stack[i].insert(name, Rc::new(RefCell::new(value)));
+        self.values.insert(name, Rc::new(RefCell::new(value)));
     }
 
-    pub(crate) fn get(&mut self, name: Token) -> EnvResult {
-        for i in (0..self.stack.len()).rev() {
-            if let Some(v) = self.stack[i].get(&name.lexeme) {
-                // this is sad, but I have to clone. I guess that's what java
-                // does?
-                return Ok(v.clone());
-            }
+    pub(crate) fn get(&self, name: Token) -> EnvResult {
+        if let Some(v) = self.values.get(&name.lexeme) {
+            return Ok(v.clone());
+        }
+        if let Some(enclosing) = &self.enclosing {
+            return enclosing.borrow().get(name);
         }
         Err(RuntimeError::new(
             format!(
@@ -79,8 +57,11 @@ impl Environment {
         ))
     }
 
-    pub(crate) fn get_at(&mut self, distance: usize, name: Token) -> EnvResult {
-        match self.stack[self.ancestor(distance)].get(&name.lexeme) {
+    pub(crate) fn get_at(&self, distance: usize, name: Token) -> EnvResult {
+        if distance > 0 {
+            return self.ancestor(distance).borrow().get_at(0, name);
+        }
+        match self.values.get(&name.lexeme) {
             Some(v) => Ok(v.clone()),
             None => Err(RuntimeError::new(
                 format!(
@@ -93,18 +74,13 @@ impl Environment {
         }
     }
 
-    pub(crate) fn assign(
-        &mut self,
-        name: Token,
-        value: Value,
-    ) -> Result<Rc<RefCell<Value>>, RuntimeError> {
-        for i in (0..self.stack.len()).rev() {
-            if self.stack[i].contains_key(&name.lexeme) {
-                let mut b =
-                    self.stack[i].get(&name.lexeme).unwrap().borrow_mut();
-                *b = value;
-                return Ok(self.stack[i].get(&name.lexeme).unwrap().clone());
-            }
+    pub(crate) fn assign(&mut self, name: Token, value: Value) -> EnvResult {
+        if let Some(v) = self.values.get(&name.lexeme) {
+            *v.borrow_mut() = value;
+            return Ok(v.clone());
+        }
+        if let Some(enclosing) = &self.enclosing {
+            return enclosing.borrow_mut().assign(name, value);
         }
         Err(RuntimeError::new(
             format!(
@@ -122,22 +98,28 @@ impl Environment {
         name: Token,
         value: Value,
     ) -> EnvResult {
+        if distance > 0 {
+            return self
+                .ancestor(distance)
+                .borrow_mut()
+                .assign_at(0, name, value);
+        }
         // looks a bit suspicious unwrapping, but I guess we know the variable
         // has been resolved from the resolver
-        let i = self.ancestor(distance);
-        let mut b = self.stack[i].get(&name.lexeme).unwrap().borrow_mut();
-        *b = value;
-        return Ok(self.stack[i].get(&name.lexeme).unwrap().clone());
-    }
-
-    /// if len is 9 and distance is 0, need to return 8, the last valid index
-    fn ancestor(&self, distance: usize) -> usize {
-        self.stack.len() - distance - 1
+        let v = self.values.get(&name.lexeme).unwrap();
+        *v.borrow_mut() = value;
+        Ok(v.clone())
     }
-}
 
-impl Default for Environment {
-    fn default() -> Self {
-        Self::new()
+    /// return the environment `distance` scopes out from `self`. panics if
+    /// `distance` is 0 since `self` isn't behind an `Rc`, or if the chain
+    /// runs out
+    fn ancestor(&self, distance: usize) -> Rc<RefCell<Environment>> {
+        let mut environment = self.enclosing.clone().unwrap();
+        for _ in 1..distance {
+            let next = environment.borrow().enclosing.clone().unwrap();
+            environment = next;
+        }
+        environment
     }
 }
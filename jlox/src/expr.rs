@@ -1,13 +1,34 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use crate::{
     token::{Literal, Token},
     token_type::TokenType,
 };
 
+/// a unique identifier for an expression the resolver can resolve. the
+/// [Interpreter](crate::interpreter::Interpreter) keys its resolved depths on
+/// this instead of on the expression itself, so two textually identical
+/// expressions on the same line still get their own entries
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct ExprId(usize);
+
+impl ExprId {
+    /// ids are drawn from a global counter rather than one owned by the
+    /// parser, so they stay unique across separate parses sharing one
+    /// interpreter
+    fn next() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub(crate) enum Expr {
     Assign {
+        id: ExprId,
         name: Token,
         value: Box<Expr>,
     },
@@ -21,6 +42,10 @@ pub(crate) enum Expr {
         paren: Token,
         arguments: Vec<Expr>,
     },
+    Get {
+        object: Box<Expr>,
+        name: Token,
+    },
     Grouping {
         expression: Box<Expr>,
     },
@@ -31,11 +56,26 @@ pub(crate) enum Expr {
         right: Box<Expr>,
     },
     Null,
+    Set {
+        object: Box<Expr>,
+        name: Token,
+        value: Box<Expr>,
+    },
+    Super {
+        id: ExprId,
+        keyword: Token,
+        method: Token,
+    },
+    This {
+        id: ExprId,
+        keyword: Token,
+    },
     Unary {
         operator: Token,
         right: Box<Expr>,
     },
     Variable {
+        id: ExprId,
         name: Token,
     },
 }
@@ -43,6 +83,7 @@ pub(crate) enum Expr {
 impl Expr {
     pub(crate) fn assign(name: Token, value: Expr) -> Self {
         Self::Assign {
+            id: ExprId::next(),
             name,
             value: Box::new(value),
         }
@@ -68,6 +109,13 @@ impl Expr {
         }
     }
 
+    pub(crate) fn get(object: Expr, name: Token) -> Self {
+        Self::Get {
+            object: Box::new(object),
+            name,
+        }
+    }
+
     pub(crate) fn grouping(expression: Expr) -> Self {
         Self::Grouping {
             expression: Box::new(expression),
@@ -86,6 +134,29 @@ impl Expr {
         }
     }
 
+    pub(crate) fn set(object: Expr, name: Token, value: Expr) -> Self {
+        Self::Set {
+            object: Box::new(object),
+            name,
+            value: Box::new(value),
+        }
+    }
+
+    pub(crate) fn sup(keyword: Token, method: Token) -> Self {
+        Self::Super {
+            id: ExprId::next(),
+            keyword,
+            method,
+        }
+    }
+
+    pub(crate) fn this(keyword: Token) -> Self {
+        Self::This {
+            id: ExprId::next(),
+            keyword,
+        }
+    }
+
     pub(crate) fn unary(operator: Token, right: Expr) -> Self {
         Self::Unary {
             operator,
@@ -94,7 +165,10 @@ impl Expr {
     }
 
     pub(crate) fn variable(name: Token) -> Self {
-        Self::Variable { name }
+        Self::Variable {
+            id: ExprId::next(),
+            name,
+        }
     }
 
     /// Returns `true` if the expr is [`Null`].
@@ -120,8 +194,8 @@ impl Display for Expr {
                 write!(f, "({} {})", operator.lexeme, right)
             }
             Expr::Null => write!(f, "nil"),
-            Expr::Variable { name } => write!(f, "{name}"),
-            Expr::Assign { name, value } => {
+            Expr::Variable { name, .. } => write!(f, "{name}"),
+            Expr::Assign { name, value, .. } => {
                 write!(f, "(assign {name} {value})")
             }
             Expr::Logical {
@@ -145,6 +219,18 @@ impl Display for Expr {
                 }
                 write!(f, ")")
             }
+            Expr::Get { object, name } => {
+                write!(f, "(get {object} {})", name.lexeme)
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => write!(f, "(set {object} {} {value})", name.lexeme),
+            Expr::Super { method, .. } => {
+                write!(f, "(super {})", method.lexeme)
+            }
+            Expr::This { .. } => write!(f, "this"),
         }
     }
 }
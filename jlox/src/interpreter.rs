@@ -2,87 +2,170 @@ use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use crate::{
     environment::Environment,
-    expr::Expr,
+    expr::{Expr, ExprId},
     stmt::Stmt,
     token::{Literal, Token},
     token_type::TokenType,
-    Lox,
 };
 
 use self::{
-    builtin::Builtin, callable::Callable, function::Function, value::Value,
+    builtin::{Arity, Builtin},
+    callable::Callable,
+    class::{Class, Instance},
+    function::Function,
+    value::Value,
 };
 
 pub(crate) mod builtin;
 mod callable;
+mod class;
 mod function;
 pub(crate) mod value;
 
-pub(crate) struct Interpreter<'a> {
-    pub(crate) lox: &'a mut Lox,
-    globals: Environment,
+pub(crate) struct Interpreter {
+    globals: Rc<RefCell<Environment>>,
 
-    /// index of the current environment in globals
-    environment: usize,
+    /// the innermost scope currently being executed
+    environment: Rc<RefCell<Environment>>,
 
-    locals: HashMap<Expr, usize>,
+    locals: HashMap<ExprId, usize>,
+
+    /// the address of the native stack where [Interpreter::interpret] was
+    /// entered, which [Interpreter::stack_used] measures from
+    stack_base: usize,
+}
+
+/// how much native stack nested Lox calls may use before reporting a stack
+/// overflow instead of overflowing the Rust stack. every Lox call passes
+/// through several recursive [Interpreter::evaluate] and
+/// [Interpreter::execute] frames, whose size depends heavily on the build, so
+/// this limits the bytes used rather than the number of calls. half of the
+/// 2 MiB that spawned threads get by default leaves the rest for the embedder
+pub(crate) const STACK_BUDGET: usize = 1 << 20;
+
+/// the address of a local in a fresh frame, approximating the current top of
+/// the native stack
+#[inline(never)]
+fn stack_address() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
 }
 
-fn clock(
-    _: &mut Environment,
-    _: Vec<Rc<RefCell<Value>>>,
-) -> Rc<RefCell<Value>> {
-    Rc::new(RefCell::new(Value::Number(
+fn clock(_: &[Value]) -> Result<Value, String> {
+    Ok(Value::Number(
         std::time::SystemTime::UNIX_EPOCH
             .elapsed()
             .unwrap()
             .as_millis() as f64
             / 1000.0,
-    )))
+    ))
 }
 
-impl<'a> Interpreter<'a> {
-    pub(crate) fn new(lox: &'a mut Lox) -> Self {
-        let mut globals = Environment::new();
-        globals.define(
-            "clock".to_owned(),
-            Value::Builtin(Builtin {
-                params: Vec::new(),
-                fun: clock,
-            }),
-        );
-        Self {
-            lox,
+impl Interpreter {
+    pub(crate) fn new() -> Self {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        let mut interpreter = Self {
+            environment: globals.clone(),
             globals,
-            environment: 0,
             locals: HashMap::new(),
-        }
+            stack_base: 0,
+        };
+        interpreter.define_native("clock", Arity::Fixed(0), clock);
+        interpreter
     }
 
-    pub(crate) fn interpret(&mut self, statements: Vec<Stmt>) {
+    /// define a global native function `name` backed by `fun`. calls with a
+    /// number of arguments not accepted by `arity` are rejected before `fun`
+    /// runs
+    pub(crate) fn define_native(
+        &mut self,
+        name: &str,
+        arity: Arity,
+        fun: impl FnMut(&[Value]) -> Result<Value, String> + 'static,
+    ) {
+        let builtin = Builtin::new(name.to_owned(), arity, fun);
+        self.set_global(name, Value::Builtin(builtin));
+    }
+
+    /// execute `statements`, stopping at the first runtime error. if the last
+    /// statement is an expression statement, its value is returned
+    pub(crate) fn interpret(
+        &mut self,
+        statements: Vec<Stmt>,
+    ) -> Result<Option<Value>, RuntimeError> {
+        self.stack_base = stack_address();
+        let mut last = None;
         for statement in statements {
-            if let Err(e) = self.execute(statement) {
-                self.lox.runtime_error(e);
+            last = None;
+            if let Stmt::Expression { expression } = statement {
+                last = Some(self.evaluate(expression)?.borrow().clone());
+            } else {
+                self.execute(statement)?.into_result()?;
             }
         }
+        Ok(last)
     }
 
-    pub(crate) fn resolve(&mut self, expr: Expr, depth: usize) {
-        self.locals.insert(expr, depth);
+    /// the number of bytes of native stack in use since
+    /// [Interpreter::interpret] was entered
+    pub(crate) fn stack_used(&self) -> usize {
+        self.stack_base.abs_diff(stack_address())
+    }
+
+    /// look up the global variable `name`, if it has been defined
+    pub(crate) fn get_global(&self, name: &str) -> Option<Value> {
+        let name = Token::new(
+            TokenType::Identifier,
+            name.to_owned(),
+            Literal::Null,
+            0,
+        );
+        let value = self.globals.borrow().get(name).ok()?;
+        let value = value.borrow().clone();
+        Some(value)
+    }
+
+    /// define the global variable `name`, overwriting any previous value
+    pub(crate) fn set_global(&mut self, name: &str, value: Value) {
+        self.globals.borrow_mut().define(name.to_owned(), value);
+    }
+
+    pub(crate) fn resolve(&mut self, id: ExprId, depth: usize) {
+        self.locals.insert(id, depth);
     }
 
     pub(crate) fn lookup_variable(
         &mut self,
         name: Token,
-        expr: &Expr,
+        id: ExprId,
     ) -> Result<Rc<RefCell<Value>>, RuntimeError> {
-        let distance = self.locals.get(expr);
+        let distance = self.locals.get(&id);
         if let Some(d) = distance {
-            self.globals.get_at(*d, name)
+            self.environment.borrow().get_at(*d, name)
         } else {
-            self.globals.get(name)
+            self.globals.borrow().get(name)
         }
     }
+
+    /// execute `statements` in `environment`, stopping early on an error or
+    /// any abrupt [Completion]. the current environment is restored afterward
+    /// either way
+    pub(crate) fn execute_block(
+        &mut self,
+        statements: Vec<Stmt>,
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<Completion, RuntimeError> {
+        let previous = std::mem::replace(&mut self.environment, environment);
+        let mut result = Ok(Completion::Normal);
+        for statement in statements {
+            result = self.execute(statement);
+            if !matches!(result, Ok(Completion::Normal)) {
+                break;
+            }
+        }
+        self.environment = previous;
+        result
+    }
 }
 
 macro_rules! with_strings {
@@ -111,113 +194,320 @@ macro_rules! with_numbers {
     };
 }
 
+/// apply a binary `operator` to operands that have already been evaluated
+fn apply_binary(
+    operator: Token,
+    left: Rc<RefCell<Value>>,
+    right: Rc<RefCell<Value>>,
+) -> Result<Rc<RefCell<Value>>, RuntimeError> {
+    match operator.typ {
+        TokenType::Minus => {
+            with_numbers!(operator, left => a, right => b);
+            Ok(Rc::new(RefCell::new(Value::Number(a - b))))
+        }
+        TokenType::Slash => {
+            with_numbers!(operator, left => a, right => b);
+            Ok(Rc::new(RefCell::new(Value::Number(a / b))))
+        }
+        TokenType::Star => {
+            with_numbers!(operator, left => a, right => b);
+            Ok(Rc::new(RefCell::new(Value::Number(a * b))))
+        }
+        TokenType::Plus => {
+            if matches!(*left.borrow(), Value::Number(_))
+                && matches!(*right.borrow(), Value::Number(_))
+            {
+                with_numbers!(operator, left => a, right => b);
+                Ok(Rc::new(RefCell::new(Value::Number(a + b))))
+            } else if matches!(*left.borrow(), Value::String(_))
+                && matches!(*right.borrow(), Value::String(_))
+            {
+                with_strings!(operator, left => a, right => b);
+                Ok(Rc::new(RefCell::new(Value::String(a + &b))))
+            } else {
+                Err(RuntimeError::new(
+                    "Operands must be two numbers or two strings.".to_string(),
+                    operator,
+                ))
+            }
+        }
+        // NOTE comparisons are only supported for numbers, but I
+        // could trivially support them for any Value by deriving
+        // PartialOrd
+        TokenType::Greater => {
+            with_numbers!(operator, left => a, right => b);
+            Ok(Rc::new(RefCell::new(Value::Boolean(a > b))))
+        }
+        TokenType::GreaterEqual => {
+            with_numbers!(operator, left => a, right => b);
+            Ok(Rc::new(RefCell::new(Value::Boolean(a >= b))))
+        }
+        TokenType::Less => {
+            with_numbers!(operator, left => a, right => b);
+            Ok(Rc::new(RefCell::new(Value::Boolean(a < b))))
+        }
+        TokenType::LessEqual => {
+            with_numbers!(operator, left => a, right => b);
+            Ok(Rc::new(RefCell::new(Value::Boolean(a <= b))))
+        }
+        TokenType::BangEqual => {
+            Ok(Rc::new(RefCell::new(Value::Boolean(!(left == right)))))
+        }
+        TokenType::EqualEqual => {
+            Ok(Rc::new(RefCell::new(Value::Boolean(left == right))))
+        }
+        _ => unreachable!(),
+    }
+}
+
 #[derive(Debug)]
-pub(crate) enum RuntimeError {
-    Error { message: String, token: Token },
-    Return(Rc<RefCell<Value>>),
+pub(crate) struct RuntimeError {
+    message: String,
+    token: Token,
 }
 
 impl RuntimeError {
     pub(crate) fn new(message: String, token: Token) -> Self {
-        Self::Error { message, token }
+        Self { message, token }
     }
 
     pub(crate) fn message(&self) -> &str {
-        match self {
-            RuntimeError::Error { message, token: _ } => message,
-            RuntimeError::Return(_) => unreachable!(),
-        }
+        &self.message
     }
 
     pub(crate) fn line(&self) -> usize {
-        match self {
-            RuntimeError::Error { message: _, token } => token.line,
-            RuntimeError::Return(_) => unreachable!(),
-        }
+        self.token.line
     }
 }
 
-impl<'a> Interpreter<'a> {
+/// how a statement finished executing, if it didn't fail with a
+/// [RuntimeError]. anything other than [Completion::Normal] skips the rest of
+/// the enclosing statements until it reaches the loop or function call that
+/// handles it. each variant keeps its keyword for reporting errors
+#[derive(Debug)]
+pub(crate) enum Completion {
+    Normal,
+    Return {
+        keyword: Token,
+        value: Rc<RefCell<Value>>,
+    },
+    Break(Token),
+    Continue(Token),
+}
+
+impl Completion {
+    /// convert a completion that no enclosing loop or function handled into
+    /// an error. the resolver rejects these statically, so this is only a
+    /// fallback in case one slips through
+    pub(crate) fn into_result(self) -> Result<(), RuntimeError> {
+        let (message, keyword) = match self {
+            Completion::Normal => return Ok(()),
+            Completion::Return { keyword, .. } => {
+                ("Can't return from top-level code.", keyword)
+            }
+            Completion::Break(keyword) => {
+                ("Can't use 'break' outside of a loop.", keyword)
+            }
+            Completion::Continue(keyword) => {
+                ("Can't use 'continue' outside of a loop.", keyword)
+            }
+        };
+        Err(RuntimeError::new(message.to_owned(), keyword))
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
     pub(crate) fn execute(
         &mut self,
         stmt: Stmt,
-    ) -> Result<Rc<RefCell<Value>>, RuntimeError> {
+    ) -> Result<Completion, RuntimeError> {
         match stmt {
-            Stmt::Expression { expression: e } => self.evaluate(e),
+            Stmt::Expression { expression: e } => {
+                self.evaluate(e)?;
+                Ok(Completion::Normal)
+            }
             Stmt::Print { expression: e } => {
                 let value = self.evaluate(e)?;
                 println!("{}", value.borrow());
-                Ok(value)
-            }
-            Stmt::Var { name, initializer } => {
-                let value = if !initializer.is_null() {
-                    self.evaluate(initializer)?
-                } else {
-                    Rc::new(RefCell::new(Value::Nil))
-                };
-                self.globals.define(name.lexeme, value.borrow().clone());
-                Ok(Rc::new(RefCell::new(Value::Nil)))
+                Ok(Completion::Normal)
             }
+            Stmt::Var { name, initializer } => self.var(name, initializer),
             Stmt::Block { statements } => {
-                self.globals.push();
-                for statement in statements {
-                    if let e @ Err(_) = self.execute(statement) {
-                        // have to reset the stack before returning in case of
-                        // error, so we can't just use ?
-                        self.globals.pop();
-                        return e;
-                    }
-                }
-                self.globals.pop();
-                Ok(Rc::new(RefCell::new(Value::Nil)))
+                let env = Environment::with_enclosing(self.environment.clone());
+                self.execute_block(statements, Rc::new(RefCell::new(env)))
             }
             Stmt::If {
                 condition,
                 then_branch,
                 else_branch,
-            } => {
-                if self.evaluate(condition)?.borrow().is_truthy() {
-                    Ok(self.execute(*then_branch)?)
-                } else if !else_branch.is_null() {
-                    Ok(self.execute(*else_branch)?)
-                } else {
-                    Ok(Rc::new(RefCell::new(Value::Nil)))
-                }
-            }
-            Stmt::Null => todo!(),
-            Stmt::While { condition, body } => {
-                // these clones feel a bit weird. letting execute and evaluate
-                // take &self seems okay as an alternative, but then I have to
-                // clone the strings and numbers instead.
-                while self.evaluate(condition.clone())?.borrow().is_truthy() {
-                    self.execute(*body.clone())?;
-                }
-                Ok(Rc::new(RefCell::new(Value::Nil)))
-            }
+            } => self.if_stmt(condition, *then_branch, *else_branch),
+            Stmt::Null => Ok(Completion::Normal),
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => self.while_stmt(condition, *body, increment),
+            Stmt::Break { keyword } => Ok(Completion::Break(keyword)),
+            Stmt::Continue { keyword } => Ok(Completion::Continue(keyword)),
             Stmt::Function { name, params, body } => {
-                let function = Function::new(
-                    Stmt::Function {
-                        name: name.clone(),
-                        params,
-                        body,
-                    },
-                    self.globals.clone(),
-                );
-                self.globals.define(name.lexeme, Value::Function(function));
-                Ok(Rc::new(RefCell::new(Value::Nil)))
+                self.function(name, params, body)
             }
-            Stmt::Return { keyword: _, value } => {
-                let ret = if !value.is_null() {
-                    self.evaluate(value)?
-                } else {
-                    Rc::new(RefCell::new(Value::Nil))
-                };
-                Err(RuntimeError::Return(ret))
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => self.class(name, superclass, methods),
+            Stmt::Return { keyword, value } => self.return_stmt(keyword, value),
+        }
+    }
+
+    fn var(
+        &mut self,
+        name: Token,
+        initializer: Expr,
+    ) -> Result<Completion, RuntimeError> {
+        let value = if !initializer.is_null() {
+            self.evaluate(initializer)?
+        } else {
+            Rc::new(RefCell::new(Value::Nil))
+        };
+        self.environment
+            .borrow_mut()
+            .define(name.lexeme, value.borrow().clone());
+        Ok(Completion::Normal)
+    }
+
+    fn if_stmt(
+        &mut self,
+        condition: Expr,
+        then_branch: Stmt,
+        else_branch: Stmt,
+    ) -> Result<Completion, RuntimeError> {
+        if self.evaluate(condition)?.borrow().is_truthy() {
+            self.execute(then_branch)
+        } else if !else_branch.is_null() {
+            self.execute(else_branch)
+        } else {
+            Ok(Completion::Normal)
+        }
+    }
+
+    fn while_stmt(
+        &mut self,
+        condition: Expr,
+        body: Stmt,
+        increment: Expr,
+    ) -> Result<Completion, RuntimeError> {
+        // these clones feel a bit weird. letting execute and evaluate
+        // take &self seems okay as an alternative, but then I have to
+        // clone the strings and numbers instead.
+        while self.evaluate(condition.clone())?.borrow().is_truthy() {
+            match self.execute(body.clone())? {
+                Completion::Normal | Completion::Continue(_) => {}
+                Completion::Break(_) => break,
+                ret @ Completion::Return { .. } => return Ok(ret),
             }
+            if !increment.is_null() {
+                self.evaluate(increment.clone())?;
+            }
+        }
+        Ok(Completion::Normal)
+    }
+
+    fn function(
+        &mut self,
+        name: Token,
+        params: Vec<Token>,
+        body: Vec<Stmt>,
+    ) -> Result<Completion, RuntimeError> {
+        let function = Function::new(
+            Stmt::Function {
+                name: name.clone(),
+                params,
+                body,
+            },
+            self.environment.clone(),
+            false,
+        );
+        self.environment
+            .borrow_mut()
+            .define(name.lexeme, Value::Function(function));
+        Ok(Completion::Normal)
+    }
+
+    fn class(
+        &mut self,
+        name: Token,
+        superclass: Expr,
+        methods: Vec<Stmt>,
+    ) -> Result<Completion, RuntimeError> {
+        let superclass = if let Expr::Variable { name: sup, .. } = &superclass {
+            let value = self.evaluate(superclass.clone())?;
+            let Value::Class(class) = value.borrow().clone() else {
+                return Err(RuntimeError::new(
+                    "Superclass must be a class.".to_owned(),
+                    sup.clone(),
+                ));
+            };
+            Some(class)
+        } else {
+            None
+        };
+
+        self.environment
+            .borrow_mut()
+            .define(name.lexeme.clone(), Value::Nil);
+
+        let enclosing = self.environment.clone();
+        if let Some(sup) = &superclass {
+            let mut env = Environment::with_enclosing(enclosing.clone());
+            env.define("super".to_owned(), Value::Class(sup.clone()));
+            self.environment = Rc::new(RefCell::new(env));
         }
+
+        let mut class_methods = HashMap::new();
+        for method in methods {
+            let Stmt::Function { name, .. } = &method else {
+                unreachable!("class methods are always functions");
+            };
+            let name = name.lexeme.clone();
+            let function =
+                Function::new(method, self.environment.clone(), name == "init");
+            class_methods.insert(name, function);
+        }
+
+        self.environment = enclosing;
+
+        let class = Class::new(name.lexeme.clone(), superclass, class_methods);
+        self.environment
+            .borrow_mut()
+            .assign(name, Value::Class(Rc::new(class)))?;
+        Ok(Completion::Normal)
+    }
+
+    fn return_stmt(
+        &mut self,
+        keyword: Token,
+        value: Expr,
+    ) -> Result<Completion, RuntimeError> {
+        let value = if !value.is_null() {
+            self.evaluate(value)?
+        } else {
+            Rc::new(RefCell::new(Value::Nil))
+        };
+        Ok(Completion::Return { keyword, value })
     }
 
-    /// consume the expression in `self` and evaluate it to a [Value]
+    /// consume the expression in `self` and evaluate it to a [Value]. most
+    /// variants are handled in their own methods, as in
+    /// [Interpreter::execute], since every nested Lox call recurses through
+    /// both and their frames add up quickly in an unoptimized build
     pub(crate) fn evaluate(
         &mut self,
         expr: Expr,
@@ -227,70 +517,7 @@ impl<'a> Interpreter<'a> {
                 left,
                 operator,
                 right,
-            } => {
-                let left = self.evaluate(*left)?;
-                let right = self.evaluate(*right)?;
-
-                match operator.typ {
-                    TokenType::Minus => {
-                        with_numbers!(operator, left => a, right => b);
-                        Ok(Rc::new(RefCell::new(Value::Number(a - b))))
-                    }
-                    TokenType::Slash => {
-                        with_numbers!(operator, left => a, right => b);
-                        Ok(Rc::new(RefCell::new(Value::Number(a / b))))
-                    }
-                    TokenType::Star => {
-                        with_numbers!(operator, left => a, right => b);
-                        Ok(Rc::new(RefCell::new(Value::Number(a * b))))
-                    }
-                    TokenType::Plus => {
-                        if matches!(*left.borrow(), Value::Number(_))
-                            && matches!(*right.borrow(), Value::Number(_))
-                        {
-                            with_numbers!(operator, left => a, right => b);
-                            Ok(Rc::new(RefCell::new(Value::Number(a + b))))
-                        } else if matches!(*left.borrow(), Value::String(_))
-                            && matches!(*right.borrow(), Value::String(_))
-                        {
-                            with_strings!(operator, left => a, right => b);
-                            Ok(Rc::new(RefCell::new(Value::String(a + &b))))
-                        } else {
-                            Err(RuntimeError::new(
-                                "Operands must be two numbers or two strings."
-                                    .to_string(),
-                                operator,
-                            ))
-                        }
-                    }
-                    // NOTE comparisons are only supported for numbers, but I
-                    // could trivially support them for any Value by deriving
-                    // PartialOrd
-                    TokenType::Greater => {
-                        with_numbers!(operator, left => a, right => b);
-                        Ok(Rc::new(RefCell::new(Value::Boolean(a > b))))
-                    }
-                    TokenType::GreaterEqual => {
-                        with_numbers!(operator, left => a, right => b);
-                        Ok(Rc::new(RefCell::new(Value::Boolean(a >= b))))
-                    }
-                    TokenType::Less => {
-                        with_numbers!(operator, left => a, right => b);
-                        Ok(Rc::new(RefCell::new(Value::Boolean(a < b))))
-                    }
-                    TokenType::LessEqual => {
-                        with_numbers!(operator, left => a, right => b);
-                        Ok(Rc::new(RefCell::new(Value::Boolean(a <= b))))
-                    }
-                    TokenType::BangEqual => Ok(Rc::new(RefCell::new(
-                        Value::Boolean(!(left == right)),
-                    ))),
-                    TokenType::EqualEqual => {
-                        Ok(Rc::new(RefCell::new(Value::Boolean(left == right))))
-                    }
-                    _ => unreachable!(),
-                }
-            }
+            } => self.binary(*left, operator, *right),
             Expr::Grouping { expression } => self.evaluate(*expression),
             Expr::Literal(l) => match l {
                 Literal::String(s) => {
@@ -307,78 +534,200 @@ impl<'a> Interpreter<'a> {
                 }
                 Literal::Null => Ok(Rc::new(RefCell::new(Value::Nil))),
             },
-            Expr::Unary { operator, right } => {
-                let right = self.evaluate(*right)?;
-                match operator.typ {
-                    TokenType::Minus => {
-                        let Value::Number(n) = *right.borrow() else {
-			    return Err(RuntimeError::new(
-				"Operand must be a number.".to_owned(),
-				operator,
-			    ))
-			};
-                        Ok(Rc::new(RefCell::new(Value::Number(-n))))
-                    }
-                    TokenType::Bang => Ok(Rc::new(RefCell::new(
-                        Value::Boolean(!right.borrow().is_truthy()),
-                    ))),
-                    _ => unreachable!(),
-                }
-            }
+            Expr::Unary { operator, right } => self.unary(operator, *right),
             Expr::Null => unreachable!(),
-            Expr::Variable { ref name } => {
-                self.lookup_variable(name.clone(), &expr)
-            }
-            Expr::Assign {
-                ref name,
-                ref value,
-            } => {
-                let value = self.evaluate(*value.clone())?;
-                let d = self.locals.get(&expr);
-                let v = value.borrow();
-                if let Some(d) = d {
-                    self.globals.assign_at(*d, name.clone(), v.clone())
-                } else {
-                    self.globals.assign(name.clone(), v.clone())
-                }
-            }
+            Expr::Variable { id, name } => self.lookup_variable(name, id),
+            Expr::Assign { id, name, value } => self.assign(id, name, *value),
+            Expr::Get { object, name } => self.get(*object, name),
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => self.set(*object, name, *value),
+            Expr::Super {
+                id,
+                keyword,
+                method,
+            } => self.super_method(id, keyword, method),
+            Expr::This { id, keyword } => self.lookup_variable(keyword, id),
             Expr::Logical {
                 left,
                 operator,
                 right,
-            } => {
-                let left = self.evaluate(*left)?;
-                if operator.typ.is_or() {
-                    if left.borrow().is_truthy() {
-                        return Ok(left);
-                    }
-                } else if !left.borrow().is_truthy() {
-                    return Ok(left);
-                }
-                self.evaluate(*right)
-            }
+            } => self.logical(*left, operator, *right),
             Expr::Call {
                 callee,
                 paren,
                 arguments,
-            } => {
-                let function = self.evaluate(*callee)?;
+            } => self.call(*callee, paren, arguments),
+        }
+    }
 
-                let mut args = Vec::new();
-                for arg in arguments {
-                    args.push(self.evaluate(arg)?);
-                }
+    /// evaluate both operands of a binary expression and apply `operator`
+    fn binary(
+        &mut self,
+        left: Expr,
+        operator: Token,
+        right: Expr,
+    ) -> Result<Rc<RefCell<Value>>, RuntimeError> {
+        let left = self.evaluate(left)?;
+        let right = self.evaluate(right)?;
+        apply_binary(operator, left, right)
+    }
 
-                let fun = function.as_ptr();
-                match unsafe { &mut *fun } {
-                    Value::Function(f) => self.finish_callable(f, args, paren),
-                    Value::Builtin(b) => self.finish_callable(b, args, paren),
-                    _ => Err(RuntimeError::new(
-                        "Can only call functions and classes.".to_owned(),
-                        paren,
-                    )),
-                }
+    fn unary(
+        &mut self,
+        operator: Token,
+        right: Expr,
+    ) -> Result<Rc<RefCell<Value>>, RuntimeError> {
+        let right = self.evaluate(right)?;
+        match operator.typ {
+            TokenType::Minus => {
+                let Value::Number(n) = *right.borrow() else {
+                    return Err(RuntimeError::new(
+                        "Operand must be a number.".to_owned(),
+                        operator,
+                    ));
+                };
+                Ok(Rc::new(RefCell::new(Value::Number(-n))))
             }
+            TokenType::Bang => Ok(Rc::new(RefCell::new(Value::Boolean(
+                !right.borrow().is_truthy(),
+            )))),
+            _ => unreachable!(),
+        }
+    }
+
+    fn assign(
+        &mut self,
+        id: ExprId,
+        name: Token,
+        value: Expr,
+    ) -> Result<Rc<RefCell<Value>>, RuntimeError> {
+        let value = self.evaluate(value)?;
+        let d = self.locals.get(&id);
+        // clone out of the borrow first since `value` may be the very
+        // cell being assigned to, as in `a = a`
+        let v = value.borrow().clone();
+        if let Some(d) = d {
+            self.environment.borrow_mut().assign_at(*d, name, v)
+        } else {
+            self.globals.borrow_mut().assign(name, v)
+        }
+    }
+
+    fn get(
+        &mut self,
+        object: Expr,
+        name: Token,
+    ) -> Result<Rc<RefCell<Value>>, RuntimeError> {
+        let object = self.evaluate(object)?;
+        let Value::Instance(instance) = &*object.borrow() else {
+            return Err(RuntimeError::new(
+                "Only instances have properties.".to_owned(),
+                name,
+            ));
+        };
+        let value = Instance::get(instance, &name)?;
+        Ok(Rc::new(RefCell::new(value)))
+    }
+
+    fn set(
+        &mut self,
+        object: Expr,
+        name: Token,
+        value: Expr,
+    ) -> Result<Rc<RefCell<Value>>, RuntimeError> {
+        let object = self.evaluate(object)?;
+        let Value::Instance(instance) = object.borrow().clone() else {
+            return Err(RuntimeError::new(
+                "Only instances have fields.".to_owned(),
+                name,
+            ));
+        };
+        let value = self.evaluate(value)?;
+        instance.borrow_mut().set(&name, value.borrow().clone());
+        Ok(value)
+    }
+
+    fn super_method(
+        &mut self,
+        id: ExprId,
+        keyword: Token,
+        method: Token,
+    ) -> Result<Rc<RefCell<Value>>, RuntimeError> {
+        // `this` is always bound in the scope just inside the one
+        // holding `super`
+        let this = Token::new(
+            TokenType::This,
+            "this".to_owned(),
+            Literal::Null,
+            keyword.line,
+        );
+        // the resolver rejects `super` outside of a subclass, so it's
+        // always resolved to a local scope
+        let d = self.locals[&id];
+        let superclass =
+            self.environment.borrow().get_at(d, keyword.clone())?;
+        let object = self.environment.borrow().get_at(d - 1, this)?;
+        let Value::Class(superclass) = superclass.borrow().clone() else {
+            unreachable!("super is always bound to a class");
+        };
+        let Value::Instance(object) = object.borrow().clone() else {
+            unreachable!("this is always bound to an instance");
+        };
+        match superclass.find_method(&method.lexeme) {
+            Some(m) => {
+                Ok(Rc::new(RefCell::new(Value::Function(m.bind(object)))))
+            }
+            None => Err(RuntimeError::new(
+                format!("Undefined property '{}'.", method.lexeme),
+                method,
+            )),
+        }
+    }
+
+    fn logical(
+        &mut self,
+        left: Expr,
+        operator: Token,
+        right: Expr,
+    ) -> Result<Rc<RefCell<Value>>, RuntimeError> {
+        let left = self.evaluate(left)?;
+        if operator.typ.is_or() {
+            if left.borrow().is_truthy() {
+                return Ok(left);
+            }
+        } else if !left.borrow().is_truthy() {
+            return Ok(left);
+        }
+        self.evaluate(right)
+    }
+
+    fn call(
+        &mut self,
+        callee: Expr,
+        paren: Token,
+        arguments: Vec<Expr>,
+    ) -> Result<Rc<RefCell<Value>>, RuntimeError> {
+        let function = self.evaluate(callee)?;
+
+        let mut args = Vec::new();
+        for arg in arguments {
+            args.push(self.evaluate(arg)?);
+        }
+
+        // call a copy of the callee so that the body is free to
+        // reassign the variable it came from
+        let callee = function.borrow().clone();
+        match callee {
+            Value::Function(mut f) => self.finish_callable(&mut f, args, paren),
+            Value::Builtin(mut b) => self.finish_callable(&mut b, args, paren),
+            Value::Class(mut c) => self.finish_callable(&mut c, args, paren),
+            _ => Err(RuntimeError::new(
+                "Can only call functions and classes.".to_owned(),
+                paren,
+            )),
         }
     }
 
@@ -388,16 +737,13 @@ impl<'a> Interpreter<'a> {
         args: Vec<Rc<RefCell<Value>>>,
         paren: Token,
     ) -> Result<Rc<RefCell<Value>>, RuntimeError> {
-        if args.len() != fun.arity() {
+        let arity = fun.arity();
+        if !arity.accepts(args.len()) {
             return Err(RuntimeError::new(
-                format!(
-                    "Expected {} arguments but got {}.",
-                    fun.arity(),
-                    args.len()
-                ),
+                format!("Expected {arity} arguments but got {}.", args.len()),
                 paren,
             ));
         }
-        fun.call(self, args)
+        fun.call(self, args, paren)
     }
 }
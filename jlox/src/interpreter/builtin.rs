@@ -1,36 +1,97 @@
 //! built-in functions
 
-use std::{cell::RefCell, fmt::Debug, rc::Rc};
+use std::{cell::RefCell, fmt::Debug, fmt::Display, rc::Rc};
 
-use crate::environment::Environment;
+use crate::token::Token;
 
 use super::{callable::Callable, Interpreter, RuntimeError, Value};
 
+/// the number of arguments a [Builtin] accepts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arity {
+    /// exactly this many arguments
+    Fixed(usize),
+
+    /// between `min` and `max` arguments, inclusive
+    Range { min: usize, max: usize },
+
+    /// at least this many arguments, with no upper limit
+    Variadic(usize),
+}
+
+impl Arity {
+    /// Returns `true` if a call with `n` arguments is allowed.
+    #[must_use]
+    pub fn accepts(&self, n: usize) -> bool {
+        match *self {
+            Arity::Fixed(k) => n == k,
+            Arity::Range { min, max } => (min..=max).contains(&n),
+            Arity::Variadic(min) => n >= min,
+        }
+    }
+}
+
+/// formatted to fit into "Expected {arity} arguments but got {n}."
+impl Display for Arity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Arity::Fixed(k) => write!(f, "{k}"),
+            Arity::Range { min, max } => write!(f, "{min} to {max}"),
+            Arity::Variadic(min) => write!(f, "at least {min}"),
+        }
+    }
+}
+
+/// the signature of a native function. returning `Err` raises a runtime error
+/// with the contained message at the line of the call
+pub(crate) type NativeFn = dyn FnMut(&[Value]) -> Result<Value, String>;
+
 #[derive(Clone)]
-#[allow(clippy::type_complexity)]
-pub(crate) struct Builtin {
-    pub(crate) params: Vec<Value>,
-    pub(crate) fun:
-        fn(&mut Environment, Vec<Rc<RefCell<Value>>>) -> Rc<RefCell<Value>>,
+pub struct Builtin {
+    pub(crate) name: String,
+    pub(crate) arity: Arity,
+    pub(crate) fun: Rc<RefCell<NativeFn>>,
+}
+
+impl Builtin {
+    pub(crate) fn new(
+        name: String,
+        arity: Arity,
+        fun: impl FnMut(&[Value]) -> Result<Value, String> + 'static,
+    ) -> Self {
+        Self {
+            name,
+            arity,
+            fun: Rc::new(RefCell::new(fun)),
+        }
+    }
 }
 
 impl Callable for Builtin {
-    fn arity(&self) -> usize {
-        self.params.len()
+    fn arity(&self) -> Arity {
+        self.arity
     }
 
     fn call(
         &mut self,
-        int: &mut Interpreter,
+        _: &mut Interpreter,
         arguments: Vec<Rc<RefCell<Value>>>,
+        paren: Token,
     ) -> Result<Rc<RefCell<Value>>, RuntimeError> {
-        Ok((self.fun)(&mut int.globals, arguments))
+        let arguments: Vec<Value> =
+            arguments.iter().map(|a| a.borrow().clone()).collect();
+        // natives can't call back into the interpreter, so this borrow can't
+        // overlap with another call to the same function
+        match (self.fun.borrow_mut())(&arguments) {
+            Ok(v) => Ok(Rc::new(RefCell::new(v))),
+            Err(message) => Err(RuntimeError::new(message, paren)),
+        }
     }
 }
 
 impl PartialEq for Builtin {
-    fn eq(&self, _other: &Self) -> bool {
-        false
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.fun, &other.fun)
     }
 }
 
@@ -1,14 +1,17 @@
 use std::{cell::RefCell, rc::Rc};
 
-use super::{RuntimeError, Value, Interpreter};
-use crate::environment::Environment;
+use super::{builtin::Arity, Interpreter, RuntimeError, Value};
+use crate::token::Token;
 
 pub(crate) trait Callable {
-    fn arity(&self) -> usize;
+    fn arity(&self) -> Arity;
 
+    /// call `self` with `arguments`. `paren` is the closing parenthesis of the
+    /// call, for reporting errors
     fn call(
         &mut self,
         int: &mut Interpreter,
         arguments: Vec<Rc<RefCell<Value>>>,
+        paren: Token,
     ) -> Result<Rc<RefCell<Value>>, RuntimeError>;
 }
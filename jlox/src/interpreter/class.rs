@@ -0,0 +1,122 @@
+//! classes and their instances
+
+use std::{cell::RefCell, collections::HashMap, fmt::Display, rc::Rc};
+
+use super::{
+    builtin::Arity, callable::Callable, function::Function, Interpreter,
+    RuntimeError, Value,
+};
+use crate::token::Token;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Class {
+    pub(crate) name: String,
+    pub(crate) superclass: Option<Rc<Class>>,
+    pub(crate) methods: HashMap<String, Function>,
+}
+
+impl Class {
+    pub(crate) fn new(
+        name: String,
+        superclass: Option<Rc<Class>>,
+        methods: HashMap<String, Function>,
+    ) -> Self {
+        Self {
+            name,
+            superclass,
+            methods,
+        }
+    }
+
+    /// look up `name` on `self`, falling back to the superclass chain
+    pub(crate) fn find_method(&self, name: &str) -> Option<&Function> {
+        self.methods
+            .get(name)
+            .or_else(|| self.superclass.as_ref()?.find_method(name))
+    }
+}
+
+/// [Callable] is implemented for `Rc<Class>` instead of `Class` itself so that
+/// each instance can hold a reference back to the class that created it
+impl Callable for Rc<Class> {
+    fn arity(&self) -> Arity {
+        match self.find_method("init") {
+            Some(init) => init.arity(),
+            None => Arity::Fixed(0),
+        }
+    }
+
+    fn call(
+        &mut self,
+        int: &mut Interpreter,
+        arguments: Vec<Rc<RefCell<Value>>>,
+        paren: Token,
+    ) -> Result<Rc<RefCell<Value>>, RuntimeError> {
+        let instance = Rc::new(RefCell::new(Instance::new(self.clone())));
+        if let Some(init) = self.find_method("init") {
+            init.bind(instance.clone()).call(int, arguments, paren)?;
+        }
+        Ok(Rc::new(RefCell::new(Value::Instance(instance))))
+    }
+}
+
+impl Display for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+#[derive(Clone)]
+pub struct Instance {
+    pub(crate) class: Rc<Class>,
+    fields: HashMap<String, Value>,
+}
+
+impl Instance {
+    pub(crate) fn new(class: Rc<Class>) -> Self {
+        Self {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+
+    /// look up the property `name` on `this`, checking fields before methods.
+    /// this takes the shared instance rather than `&self` because methods
+    /// need a reference to `this` to bind to
+    pub(crate) fn get(
+        this: &Rc<RefCell<Instance>>,
+        name: &Token,
+    ) -> Result<Value, RuntimeError> {
+        let instance = this.borrow();
+        if let Some(v) = instance.fields.get(&name.lexeme) {
+            return Ok(v.clone());
+        }
+
+        if let Some(method) = instance.class.find_method(&name.lexeme) {
+            return Ok(Value::Function(method.bind(this.clone())));
+        }
+
+        Err(RuntimeError::new(
+            format!("Undefined property '{}'.", name.lexeme),
+            name.clone(),
+        ))
+    }
+
+    pub(crate) fn set(&mut self, name: &Token, value: Value) {
+        self.fields.insert(name.lexeme.clone(), value);
+    }
+}
+
+// the fields of an instance can refer back to the instance itself, so the
+// derived Debug could recurse forever
+impl std::fmt::Debug for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+impl Display for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
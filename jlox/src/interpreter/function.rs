@@ -1,72 +1,123 @@
+use super::builtin::Arity;
 use super::callable::Callable;
+use super::class::Instance;
+use super::Completion;
 use super::Interpreter;
 use super::RuntimeError;
 use super::Value;
+use super::STACK_BUDGET;
 use crate::environment::Environment;
 use crate::stmt::Stmt;
-use crate::token::Token;
+use crate::token::{Literal, Token};
+use crate::token_type::TokenType;
 use std::cell::RefCell;
-use std::fmt::Display;
+use std::fmt::{Debug, Display};
 use std::rc::Rc;
 
-#[derive(Clone, Debug, PartialEq)]
-pub(crate) struct Function {
+#[derive(Clone)]
+pub struct Function {
     pub(crate) name: String,
     pub(crate) params: Vec<Token>,
     pub(crate) body: Vec<Stmt>,
-    pub(crate) closure: Environment,
+    pub(crate) closure: Rc<RefCell<Environment>>,
+    pub(crate) is_initializer: bool,
 }
 
 impl Function {
-    pub(crate) fn new(declaration: Stmt, closure: Environment) -> Self {
+    pub(crate) fn new(
+        declaration: Stmt,
+        closure: Rc<RefCell<Environment>>,
+        is_initializer: bool,
+    ) -> Self {
         let Stmt::Function { name, params, body } = declaration else {
-	        panic!("attempted to call non-function {declaration:?}");
-	    };
+            panic!("attempted to call non-function {declaration:?}");
+        };
         Self {
             name: name.lexeme,
             params,
             body,
             closure,
+            is_initializer,
         }
     }
+
+    /// return a copy of `self` whose closure has an extra scope with `this`
+    /// bound to `instance`
+    pub(crate) fn bind(&self, instance: Rc<RefCell<Instance>>) -> Function {
+        let mut closure = Environment::with_enclosing(self.closure.clone());
+        closure.define("this".to_owned(), Value::Instance(instance));
+        Function {
+            name: self.name.clone(),
+            params: self.params.clone(),
+            body: self.body.clone(),
+            closure: Rc::new(RefCell::new(closure)),
+            is_initializer: self.is_initializer,
+        }
+    }
+
+    /// look up `this` in the innermost scope of the closure, where
+    /// [Function::bind] put it
+    fn this(&self) -> Result<Rc<RefCell<Value>>, RuntimeError> {
+        let keyword =
+            Token::new(TokenType::This, "this".to_owned(), Literal::Null, 0);
+        self.closure.borrow().get_at(0, keyword)
+    }
 }
 
 impl Callable for Function {
-    fn arity(&self) -> usize {
-        self.params.len()
+    fn arity(&self) -> Arity {
+        Arity::Fixed(self.params.len())
     }
 
     fn call(
         &mut self,
         int: &mut Interpreter,
         arguments: Vec<Rc<RefCell<Value>>>,
+        paren: Token,
     ) -> Result<Rc<RefCell<Value>>, RuntimeError> {
-        // clone the outer environment, append the closure's stack to it, then
-        // call function. restore the closure at the end
-        let mut env = int.globals.clone();
-        let start = env.stack.len();
-        env.stack.extend(std::mem::take(&mut self.closure.stack));
-        env.push();
-        // stupid but satisfies clippy
-        (0..self.params.len()).for_each(|i| {
-            env.define(
-                self.params[i].lexeme.clone(),
-                arguments[i].borrow().clone(),
-            );
-        });
-        let tmp = std::mem::take(&mut int.globals);
-        int.globals = env;
-        let res = int.execute(Stmt::block(self.body.clone()));
-        int.globals.pop();
-        self.closure.stack = int.globals.stack[start..].to_owned();
-        int.globals = tmp;
-        match res {
-            ok @ Ok(_) => ok,
-            Err(e) => match e {
-                RuntimeError::Error { .. } => Err(e),
-                RuntimeError::Return(v) => Ok(v),
-            },
+        if int.stack_used() > STACK_BUDGET {
+            return Err(RuntimeError::new("Stack overflow.".to_owned(), paren));
+        }
+        let mut env = Environment::with_enclosing(self.closure.clone());
+        for (param, arg) in self.params.iter().zip(arguments) {
+            env.define(param.lexeme.clone(), arg.borrow().clone());
+        }
+        let completion =
+            int.execute_block(self.body.clone(), Rc::new(RefCell::new(env)))?;
+        let value = match completion {
+            Completion::Return { value, .. } => value,
+            other => {
+                other.into_result()?;
+                Rc::new(RefCell::new(Value::Nil))
+            }
+        };
+        if self.is_initializer {
+            return self.this();
         }
+        Ok(value)
+    }
+}
+
+// functions compare by identity, which for us means the same declaration
+// closing over the same environment
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.params == other.params
+            && self.body == other.body
+            && Rc::ptr_eq(&self.closure, &other.closure)
+    }
+}
+
+// the closure can contain the function itself, so leave it out to avoid
+// infinite recursion
+impl Debug for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Function")
+            .field("name", &self.name)
+            .field("params", &self.params)
+            .field("body", &self.body)
+            .finish_non_exhaustive()
     }
 }
 
@@ -1,9 +1,13 @@
-use std::fmt::Display;
+use std::{cell::RefCell, fmt::Display, rc::Rc};
 
-use super::{builtin::Builtin, function::Function};
+use super::{
+    builtin::Builtin,
+    class::{Class, Instance},
+    function::Function,
+};
 
-#[derive(Clone, Debug, PartialEq)]
-pub(crate) enum Value {
+#[derive(Clone, Debug)]
+pub enum Value {
     Nil,
     Boolean(bool),
     Number(f64),
@@ -12,6 +16,8 @@ pub(crate) enum Value {
     // I've tried was a disaster with generics
     Function(Function),
     Builtin(Builtin),
+    Class(Rc<Class>),
+    Instance(Rc<RefCell<Instance>>),
 }
 
 impl Value {
@@ -25,6 +31,23 @@ impl Value {
     }
 }
 
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Function(a), Value::Function(b)) => a == b,
+            (Value::Builtin(a), Value::Builtin(b)) => a == b,
+            // classes and instances compare by identity, not by contents
+            (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+            (Value::Instance(a), Value::Instance(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -34,6 +57,8 @@ impl Display for Value {
             Value::String(s) => write!(f, "{s}"),
             Value::Function(fun) => write!(f, "{fun}"),
             Value::Builtin(b) => write!(f, "{b:?}"),
+            Value::Class(c) => write!(f, "{c}"),
+            Value::Instance(i) => write!(f, "{}", i.borrow()),
         }
     }
 }
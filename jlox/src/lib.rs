@@ -4,6 +4,7 @@ use std::{
     cell::RefCell,
     collections::HashMap,
     error::Error,
+    fmt::Display,
     fs::read_to_string,
     io::{stdout, BufRead, BufReader, Write},
     rc::Rc,
@@ -11,7 +12,7 @@ use std::{
 
 use environment::Environment;
 use expr::Expr;
-use interpreter::{builtin::Builtin, value::Value, Interpreter, RuntimeError};
+use interpreter::{builtin::Builtin, Interpreter, RuntimeError};
 use parser::Parser;
 use resolver::Resolver;
 use scanner::Scanner;
@@ -28,12 +29,54 @@ mod stmt;
 mod token;
 mod token_type;
 
+pub use interpreter::{builtin::Arity, value::Value};
+
 type RunRes = Result<(), Box<dyn Error>>;
 
+/// an error from [Lox::eval]
+#[derive(Debug)]
+pub enum LoxError {
+    /// errors reported while scanning, parsing, or resolving. the program is
+    /// not run at all if any of these occur. each entry is one formatted
+    /// diagnostic
+    Static(Vec<String>),
+
+    /// an error encountered while running the program
+    Runtime { message: String, line: usize },
+}
+
+impl Display for LoxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoxError::Static(errors) => write!(f, "{}", errors.join("\n")),
+            LoxError::Runtime { message, line } => {
+                write!(f, "{message}\n[line {line}]")
+            }
+        }
+    }
+}
+
+impl Error for LoxError {}
+
+impl From<RuntimeError> for LoxError {
+    fn from(error: RuntimeError) -> Self {
+        Self::Runtime {
+            message: error.message().to_owned(),
+            line: error.line(),
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct Lox {
     had_error: bool,
     had_runtime_error: bool,
+
+    /// static errors reported during the current call to [Lox::eval]
+    errors: Vec<String>,
+
+    /// kept around between runs so that definitions persist
+    interpreter: Interpreter,
 }
 
 impl Lox {
@@ -41,11 +84,77 @@ impl Lox {
         Self {
             had_error: false,
             had_runtime_error: false,
+            errors: Vec::new(),
+            interpreter: Interpreter::new(),
         }
     }
 
+    /// run `source` and return the value of its final statement if that is an
+    /// expression statement, or [Value::Nil] otherwise. unlike
+    /// [Lox::run_file], errors are returned instead of printed, and the
+    /// process never exits. globals defined by `source` remain visible to
+    /// later calls. deep recursion is reported as a runtime error, even on a
+    /// thread with the default stack size
+    pub fn eval(&mut self, source: &str) -> Result<Value, LoxError> {
+        let value = self.execute(source, false)?;
+        Ok(value.unwrap_or(Value::Nil))
+    }
+
+    /// the shared implementation of [Lox::eval] and the REPL. the value of a
+    /// trailing expression statement is returned as `Some`. if `repl` is true,
+    /// that final expression may also omit its semicolon
+    fn execute(
+        &mut self,
+        source: &str,
+        repl: bool,
+    ) -> Result<Option<Value>, LoxError> {
+        self.had_error = false;
+        self.errors.clear();
+
+        let mut scanner = Scanner::new(source.to_owned(), self);
+        let tokens = scanner.scan_tokens();
+        let mut parser = Parser::new(tokens, self, repl);
+        let statements = parser.parse();
+
+        if self.had_error {
+            return Err(LoxError::Static(std::mem::take(&mut self.errors)));
+        }
+
+        let mut resolver = Resolver::new(self);
+        resolver.resolve(&statements);
+        if self.had_error {
+            return Err(LoxError::Static(std::mem::take(&mut self.errors)));
+        }
+
+        Ok(self.interpreter.interpret(statements)?)
+    }
+
+    /// return the value of the global variable `name`, if it is defined
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.interpreter.get_global(name)
+    }
+
+    /// define the global variable `name` as `value`, replacing any existing
+    /// definition
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.interpreter.set_global(name, value);
+    }
+
+    /// define a global native function `name` that Lox code can call like any
+    /// other function. `fun` receives the evaluated arguments, whose count has
+    /// already been checked against `arity`. returning `Err` raises a Lox
+    /// runtime error with that message at the line of the call
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: Arity,
+        fun: impl FnMut(&[Value]) -> Result<Value, String> + 'static,
+    ) {
+        self.interpreter.define_native(name, arity, fun);
+    }
+
     pub fn run_file(&mut self, path: &str) -> RunRes {
-        self.run(&read_to_string(path)?);
+        self.run(&read_to_string(path)?, false);
         if self.had_error {
             std::process::exit(65);
         }
@@ -55,44 +164,42 @@ impl Lox {
         Ok(())
     }
 
+    /// run an interactive session. definitions persist from one input to the
+    /// next, even after an error, and the value of a final expression
+    /// statement is printed. input continues onto following lines while it
+    /// has unclosed braces, parentheses, or strings
     pub fn run_prompt(&mut self) -> RunRes {
         let mut input = BufReader::new(std::io::stdin());
         let mut line = String::new();
         loop {
-            print!("> ");
+            print!("{}", if line.is_empty() { "> " } else { "... " });
             stdout().flush().unwrap();
             // okay to return on this error because it means there was an error
             // reading from stdin, not a language error
             match input.read_line(&mut line) {
-                Ok(n) if n == 0 => return Ok(()),
+                Ok(0) => return Ok(()),
                 Ok(_) => {}
                 Err(err) => return Err(Box::new(err)),
             };
-            self.run(&line);
+            if incomplete(&line) {
+                continue;
+            }
+            self.run(&line, true);
             self.had_error = false;
             line.clear();
         }
     }
 
-    fn run(&mut self, s: &str) {
-        let mut scanner = Scanner::new(s.to_owned(), self);
-        let tokens = scanner.scan_tokens();
-        let mut parser = Parser::new(tokens, self);
-        let statements = parser.parse();
-
-        if self.had_error {
-            return;
+    fn run(&mut self, s: &str, repl: bool) {
+        match self.execute(s, repl) {
+            Ok(Some(value)) if repl => println!("{value}"),
+            Ok(_) => {}
+            Err(e @ LoxError::Static(_)) => eprintln!("{e}"),
+            Err(e @ LoxError::Runtime { .. }) => {
+                eprintln!("{e}");
+                self.had_runtime_error = true;
+            }
         }
-
-        let mut interpreter = Interpreter::new(self);
-
-        // let mut resolver = Resolver::new(&mut interpreter);
-        // resolver.resolve(&statements);
-	if interpreter.lox.had_error {
-	    return;
-	}
-
-        interpreter.interpret(statements);
     }
 
     fn error(&mut self, line: usize, message: &str) {
@@ -100,7 +207,8 @@ impl Lox {
     }
 
     fn report(&mut self, line: usize, wher: &str, message: &str) {
-        eprintln!("[line {line}] Error{wher}: {message}");
+        self.errors
+            .push(format!("[line {line}] Error{wher}: {message}"));
         self.had_error = true;
     }
 
@@ -115,9 +223,208 @@ impl Lox {
             );
         }
     }
+}
+
+/// report whether `source` is unfinished, either because it has more opening
+/// than closing braces and parentheses or because it ends inside of a string
+fn incomplete(source: &str) -> bool {
+    let mut depth = 0;
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // the guard consumes the string through its closing quote, if any
+            '"' if !chars.by_ref().any(|c| c == '"') => return true,
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '(' | '{' => depth += 1,
+            ')' | '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    depth > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eval_returns_last_expression() {
+        let mut lox = Lox::new();
+        assert_eq!(lox.eval("1 + 2;").unwrap(), Value::Number(3.0));
+        assert_eq!(
+            lox.eval("var s = \"a\"; s + \"b\";").unwrap(),
+            Value::String("ab".to_owned())
+        );
+        assert_eq!(lox.eval("var x = 1;").unwrap(), Value::Nil);
+        assert_eq!(lox.eval("1; print \"\";").unwrap(), Value::Nil);
+        assert_eq!(lox.eval("").unwrap(), Value::Nil);
+    }
+
+    #[test]
+    fn eval_persists_definitions() {
+        let mut lox = Lox::new();
+        lox.eval("var n = 1; fun inc() { n = n + 1; return n; }")
+            .unwrap();
+        assert_eq!(lox.eval("inc();").unwrap(), Value::Number(2.0));
+        assert_eq!(lox.eval("inc();").unwrap(), Value::Number(3.0));
+
+        // a runtime error leaves earlier definitions in place
+        assert!(lox.eval("var m = 5; nil();").is_err());
+        assert_eq!(lox.eval("m + n;").unwrap(), Value::Number(8.0));
+    }
+
+    #[test]
+    fn globals() {
+        let mut lox = Lox::new();
+        assert_eq!(lox.get_global("x"), None);
+        lox.set_global("x", Value::Number(4.0));
+        assert_eq!(lox.eval("x * 2;").unwrap(), Value::Number(8.0));
+        lox.eval("x = \"four\";").unwrap();
+        assert_eq!(lox.get_global("x"), Some(Value::String("four".to_owned())));
+        lox.set_global("x", Value::Nil);
+        assert_eq!(lox.get_global("x"), Some(Value::Nil));
+    }
+
+    #[test]
+    fn static_errors() {
+        let mut lox = Lox::new();
+        let Err(LoxError::Static(errors)) = lox.eval("print 1 +; var;") else {
+            panic!("expected a static error");
+        };
+        assert_eq!(
+            errors,
+            [
+                "[line 1] Error at ';': Expect expression.",
+                "[line 1] Error at ';': Expect variable name.",
+            ]
+        );
+
+        // nothing runs if the resolver rejects the program
+        let source = "var a = 1; { var a = a; }";
+        assert!(matches!(lox.eval(source), Err(LoxError::Static(_))));
+        assert_eq!(lox.get_global("a"), None);
+    }
+
+    #[test]
+    fn runtime_errors() {
+        let mut lox = Lox::new();
+        let Err(LoxError::Runtime { message, line }) =
+            lox.eval("var a = 1;\n-\"x\";")
+        else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(message, "Operand must be a number.");
+        assert_eq!(line, 2);
+        assert_eq!(lox.get_global("a"), Some(Value::Number(1.0)));
+    }
+
+    #[test]
+    fn stack_overflow() {
+        let mut lox = Lox::new();
+        let Err(LoxError::Runtime { message, line }) =
+            lox.eval("fun r() {\n return r();\n}\nr();")
+        else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(message, "Stack overflow.");
+        assert_eq!(line, 2);
+
+        let Err(LoxError::Runtime { message, .. }) =
+            lox.eval("class A { m() { return this.m(); } } A().m();")
+        else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(message, "Stack overflow.");
+
+        // recursion within the limit still works after an overflow
+        lox.eval("fun f(n) { if (n == 0) return 0; return 1 + f(n - 1); }")
+            .unwrap();
+        assert_eq!(lox.eval("f(20);").unwrap(), Value::Number(20.0));
+    }
+
+    #[test]
+    fn arity() {
+        assert!(Arity::Fixed(2).accepts(2));
+        assert!(!Arity::Fixed(2).accepts(1));
+        assert!(!Arity::Fixed(2).accepts(3));
+
+        let range = Arity::Range { min: 1, max: 3 };
+        assert!(!range.accepts(0));
+        assert!(range.accepts(1));
+        assert!(range.accepts(3));
+        assert!(!range.accepts(4));
+
+        assert!(!Arity::Variadic(1).accepts(0));
+        assert!(Arity::Variadic(1).accepts(1));
+        assert!(Arity::Variadic(1).accepts(100));
+
+        assert_eq!(Arity::Fixed(2).to_string(), "2");
+        assert_eq!(range.to_string(), "1 to 3");
+        assert_eq!(Arity::Variadic(1).to_string(), "at least 1");
+    }
+
+    #[test]
+    fn natives() {
+        let mut lox = Lox::new();
+        lox.define_native("sum", Arity::Variadic(0), |args| {
+            let mut sum = 0.0;
+            for arg in args {
+                let Value::Number(n) = arg else {
+                    return Err("Arguments must be numbers.".to_owned());
+                };
+                sum += n;
+            }
+            Ok(Value::Number(sum))
+        });
+        assert_eq!(lox.eval("sum();").unwrap(), Value::Number(0.0));
+        assert_eq!(lox.eval("sum(1, 2, 3);").unwrap(), Value::Number(6.0));
+        assert_eq!(lox.eval("sum;").unwrap().to_string(), "<native fn>");
+
+        // errors from the native are reported at the line of the call
+        let Err(LoxError::Runtime { message, line }) =
+            lox.eval("\nsum(1, \"2\");")
+        else {
+            panic!("expected a runtime error");
+        };
+        assert_eq!(message, "Arguments must be numbers.");
+        assert_eq!(line, 2);
+
+        // natives can keep state between calls
+        let mut count = 0.0;
+        lox.define_native("count", Arity::Fixed(0), move |_| {
+            count += 1.0;
+            Ok(Value::Number(count))
+        });
+        assert_eq!(lox.eval("count(); count();").unwrap(), Value::Number(2.0));
+    }
+
+    #[test]
+    fn native_arity() {
+        let mut lox = Lox::new();
+        let calls = Rc::new(std::cell::Cell::new(0));
+        let counter = calls.clone();
+        lox.define_native("pad", Arity::Range { min: 1, max: 2 }, move |_| {
+            counter.set(counter.get() + 1);
+            Ok(Value::Nil)
+        });
+        lox.eval("pad(1); pad(1, 2);").unwrap();
+        assert_eq!(calls.get(), 2);
+
+        for (source, expected) in [
+            ("pad();", "Expected 1 to 2 arguments but got 0."),
+            ("pad(1, 2, 3);", "Expected 1 to 2 arguments but got 3."),
+            ("clock(1);", "Expected 0 arguments but got 1."),
+        ] {
+            let Err(LoxError::Runtime { message, .. }) = lox.eval(source)
+            else {
+                panic!("expected a runtime error from {source}");
+            };
+            assert_eq!(message, expected);
+        }
 
-    fn runtime_error(&mut self, error: RuntimeError) {
-        eprintln!("{}\n[line {}]", error.message(), error.line());
-        self.had_runtime_error = true;
+        // the native never runs when the argument count is rejected
+        assert_eq!(calls.get(), 2);
     }
 }
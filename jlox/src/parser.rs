@@ -15,14 +15,23 @@ pub(crate) struct Parser<'a> {
     lox: &'a mut Lox,
     tokens: Vec<Token>,
     current: usize,
+
+    /// allow the final expression statement to omit its semicolon, so the REPL
+    /// can evaluate bare expressions
+    repl: bool,
 }
 
 impl<'a> Parser<'a> {
-    pub(crate) fn new(tokens: Vec<Token>, lox: &'a mut Lox) -> Self {
+    pub(crate) fn new(
+        tokens: Vec<Token>,
+        lox: &'a mut Lox,
+        repl: bool,
+    ) -> Self {
         Self {
             lox,
             tokens,
             current: 0,
+            repl,
         }
     }
 
@@ -38,7 +47,9 @@ impl<'a> Parser<'a> {
     }
 
     fn declaration(&mut self) -> Result<Stmt, ParseError> {
-        let r = if self.matches(&[TokenType::Fun]) {
+        let r = if self.matches(&[TokenType::Class]) {
+            self.class_declaration()
+        } else if self.matches(&[TokenType::Fun]) {
             self.function("function")
         } else if self.matches(&[TokenType::Var]) {
             self.var_declaration()
@@ -51,6 +62,26 @@ impl<'a> Parser<'a> {
         r
     }
 
+    fn class_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let name = self.consume(TokenType::Identifier, "Expect class name.")?;
+
+        let mut superclass = Expr::Null;
+        if self.matches(&[TokenType::Less]) {
+            self.consume(TokenType::Identifier, "Expect superclass name.")?;
+            superclass = Expr::variable(self.previous());
+        }
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
+
+        let mut methods = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.at_end() {
+            methods.push(self.function("method")?);
+        }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.")?;
+        Ok(Stmt::class(name, superclass, methods))
+    }
+
     /// TODO consider making `kind` an enum that implements Display, so the
     /// actual kinds are encoded in the types. it's only used for error
     /// messages, so it's not really a big deal though
@@ -105,7 +136,15 @@ impl<'a> Parser<'a> {
     }
 
     fn statement(&mut self) -> Result<Stmt, ParseError> {
-        if self.matches(&[TokenType::For]) {
+        if self.matches(&[TokenType::Break]) {
+            let keyword = self.previous();
+            self.consume(TokenType::Semicolon, "Expect ';' after 'break'.")?;
+            Ok(Stmt::Break { keyword })
+        } else if self.matches(&[TokenType::Continue]) {
+            let keyword = self.previous();
+            self.consume(TokenType::Semicolon, "Expect ';' after 'continue'.")?;
+            Ok(Stmt::Continue { keyword })
+        } else if self.matches(&[TokenType::For]) {
             self.for_statement()
         } else if self.matches(&[TokenType::If]) {
             self.if_statement()
@@ -157,15 +196,7 @@ impl<'a> Parser<'a> {
         };
         self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
 
-        let mut body = self.statement()?;
-        if !increment.is_null() {
-            body = Stmt::block(vec![
-                body,
-                Stmt::Expression {
-                    expression: increment,
-                },
-            ]);
-        }
+        let body = self.statement()?;
 
         let condition = if condition.is_null() {
             Expr::Literal(Literal::True)
@@ -173,9 +204,12 @@ impl<'a> Parser<'a> {
             condition
         };
 
-        body = Stmt::While {
+        // the increment is kept on the loop itself rather than appended to
+        // the body, so that `continue` doesn't skip it
+        let mut body = Stmt::While {
             condition,
             body: Box::new(body),
+            increment,
         };
 
         if !initializer.is_null() {
@@ -196,6 +230,7 @@ impl<'a> Parser<'a> {
         Ok(Stmt::While {
             condition,
             body: Box::new(body),
+            increment: Expr::Null,
         })
     }
 
@@ -234,6 +269,9 @@ impl<'a> Parser<'a> {
 
     fn expression_statement(&mut self) -> Result<Stmt, ParseError> {
         let value = self.expression()?;
+        if self.repl && self.at_end() {
+            return Ok(Stmt::Expression { expression: value });
+        }
         self.consume(TokenType::Semicolon, "Expect ';' after expression.")?;
         Ok(Stmt::Expression { expression: value })
     }
@@ -248,8 +286,14 @@ impl<'a> Parser<'a> {
         if self.matches(&[TokenType::Equal]) {
             let equals = self.previous();
             let value = self.assignment()?;
-            if let Expr::Variable { name } = expr {
-                return Ok(Expr::assign(name, value));
+            match expr {
+                Expr::Variable { name, .. } => {
+                    return Ok(Expr::assign(name, value));
+                }
+                Expr::Get { object, name } => {
+                    return Ok(Expr::set(*object, name, value));
+                }
+                _ => {}
             }
             self.error(equals, "Invalid assignment target.");
         }
@@ -345,6 +389,12 @@ impl<'a> Parser<'a> {
         loop {
             if self.matches(&[TokenType::LeftParen]) {
                 expr = self.finish_call(expr)?;
+            } else if self.matches(&[TokenType::Dot]) {
+                let name = self.consume(
+                    TokenType::Identifier,
+                    "Expect property name after '.'.",
+                )?;
+                expr = Expr::get(expr, name);
             } else {
                 break;
             }
@@ -391,6 +441,20 @@ impl<'a> Parser<'a> {
             return Ok(Expr::literal(self.previous().literal));
         }
 
+        if self.matches(&[TokenType::Super]) {
+            let keyword = self.previous();
+            self.consume(TokenType::Dot, "Expect '.' after 'super'.")?;
+            let method = self.consume(
+                TokenType::Identifier,
+                "Expect superclass method name.",
+            )?;
+            return Ok(Expr::sup(keyword, method));
+        }
+
+        if self.matches(&[TokenType::This]) {
+            return Ok(Expr::this(self.previous()));
+        }
+
         if self.matches(&[TokenType::Identifier]) {
             return Ok(Expr::variable(self.previous()));
         }
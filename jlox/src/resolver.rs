@@ -1,7 +1,10 @@
 use std::{collections::HashMap, ops::Index};
 
 use crate::{
-    expr::Expr, interpreter::Interpreter, stmt::Stmt, token::Token, Lox,
+    expr::{Expr, ExprId},
+    stmt::Stmt,
+    token::Token,
+    Lox,
 };
 
 use stack::Stack;
@@ -12,6 +15,8 @@ mod stack;
 enum FunctionType {
     None,
     Function,
+    Initializer,
+    Method,
 }
 
 impl FunctionType {
@@ -22,23 +27,65 @@ impl FunctionType {
     fn is_none(&self) -> bool {
         matches!(self, Self::None)
     }
+
+    /// Returns `true` if the function type is [`Initializer`].
+    ///
+    /// [`Initializer`]: FunctionType::Initializer
+    #[must_use]
+    fn is_initializer(&self) -> bool {
+        matches!(self, Self::Initializer)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+impl ClassType {
+    /// Returns `true` if the class type is [`None`].
+    ///
+    /// [`None`]: ClassType::None
+    #[must_use]
+    fn is_none(&self) -> bool {
+        matches!(self, Self::None)
+    }
+
+    /// Returns `true` if the class type is [`Subclass`].
+    ///
+    /// [`Subclass`]: ClassType::Subclass
+    #[must_use]
+    fn is_subclass(&self) -> bool {
+        matches!(self, Self::Subclass)
+    }
 }
 
-pub(crate) struct Resolver<'a, 'b> {
-    /// interpreter field from java code
-    interpreter: &'a mut Interpreter<'b>,
+pub(crate) struct Resolver<'a> {
+    /// stands in for the interpreter field from the java code. going through
+    /// [Lox] gives access to both the interpreter and error reporting
+    lox: &'a mut Lox,
 
     scopes: Stack<HashMap<String, bool>>,
 
     current_function: FunctionType,
+
+    current_class: ClassType,
+
+    /// how many loops enclose the current statement within the current
+    /// function, for rejecting stray `break` and `continue`
+    loop_depth: usize,
 }
 
-impl<'a, 'b> Resolver<'a, 'b> {
-    pub(crate) fn new(interpreter: &'a mut Interpreter<'b>) -> Self {
+impl<'a> Resolver<'a> {
+    pub(crate) fn new(lox: &'a mut Lox) -> Self {
         Self {
-            interpreter,
+            lox,
             scopes: Stack::new(),
             current_function: FunctionType::None,
+            current_class: ClassType::None,
+            loop_depth: 0,
         }
     }
 
@@ -55,6 +102,54 @@ impl<'a, 'b> Resolver<'a, 'b> {
                 self.resolve(statements);
                 self.end_scope();
             }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                let enclosing = self.current_class;
+                self.current_class = ClassType::Class;
+
+                self.declare(name);
+                self.define(name);
+
+                if let Expr::Variable { name: sup, .. } = superclass {
+                    if sup.lexeme == name.lexeme {
+                        self.lox.parse_error(
+                            sup.clone(),
+                            "A class can't inherit from itself.",
+                        );
+                    }
+                    self.current_class = ClassType::Subclass;
+                    self.resolve_expr(superclass);
+
+                    self.begin_scope();
+                    self.scopes.peek().insert("super".to_owned(), true);
+                }
+
+                self.begin_scope();
+                self.scopes.peek().insert("this".to_owned(), true);
+
+                for method in methods {
+                    let Stmt::Function { name, params, body } = method else {
+                        unreachable!("class methods are always functions");
+                    };
+                    let typ = if name.lexeme == "init" {
+                        FunctionType::Initializer
+                    } else {
+                        FunctionType::Method
+                    };
+                    self.resolve_function(params, body, typ);
+                }
+
+                self.end_scope();
+
+                if !superclass.is_null() {
+                    self.end_scope();
+                }
+
+                self.current_class = enclosing;
+            }
             Stmt::Expression { expression } => {
                 self.resolve_expr(expression);
             }
@@ -80,12 +175,18 @@ impl<'a, 'b> Resolver<'a, 'b> {
             }
             Stmt::Return { keyword, value } => {
                 if self.current_function.is_none() {
-                    self.interpreter.lox.error(
-                        keyword.line,
-                        "Can't return from top-level code",
+                    self.lox.parse_error(
+                        keyword.clone(),
+                        "Can't return from top-level code.",
                     );
                 }
                 if !value.is_null() {
+                    if self.current_function.is_initializer() {
+                        self.lox.parse_error(
+                            keyword.clone(),
+                            "Can't return a value from an initializer.",
+                        );
+                    }
                     self.resolve_expr(value);
                 }
             }
@@ -96,9 +197,34 @@ impl<'a, 'b> Resolver<'a, 'b> {
                 }
                 self.define(name);
             }
-            Stmt::While { condition, body } => {
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => {
                 self.resolve_expr(condition);
+                self.loop_depth += 1;
                 self.resolve_stmt(body);
+                self.loop_depth -= 1;
+                if !increment.is_null() {
+                    self.resolve_expr(increment);
+                }
+            }
+            Stmt::Break { keyword } => {
+                if self.loop_depth == 0 {
+                    self.lox.parse_error(
+                        keyword.clone(),
+                        "Can't use 'break' outside of a loop.",
+                    );
+                }
+            }
+            Stmt::Continue { keyword } => {
+                if self.loop_depth == 0 {
+                    self.lox.parse_error(
+                        keyword.clone(),
+                        "Can't use 'continue' outside of a loop.",
+                    );
+                }
             }
         }
     }
@@ -111,6 +237,8 @@ impl<'a, 'b> Resolver<'a, 'b> {
     ) {
         let enclosing = self.current_function;
         self.current_function = typ;
+        // a loop around a function declaration doesn't enclose its body
+        let enclosing_loops = std::mem::take(&mut self.loop_depth);
         self.begin_scope();
         for param in params {
             self.declare(param);
@@ -118,14 +246,15 @@ impl<'a, 'b> Resolver<'a, 'b> {
         }
         self.resolve(body);
         self.end_scope();
+        self.loop_depth = enclosing_loops;
         self.current_function = enclosing;
     }
 
     fn resolve_expr(&mut self, expr: &Expr) {
         match expr {
-            Expr::Assign { name, value } => {
+            Expr::Assign { id, name, value } => {
                 self.resolve_expr(value);
-                self.resolve_local(expr, name);
+                self.resolve_local(*id, name);
             }
             Expr::Binary {
                 left,
@@ -146,6 +275,9 @@ impl<'a, 'b> Resolver<'a, 'b> {
                     self.resolve_expr(arg);
                 }
             }
+            Expr::Get { object, name: _ } => {
+                self.resolve_expr(object);
+            }
             Expr::Grouping { expression } => {
                 self.resolve_expr(expression);
             }
@@ -159,28 +291,64 @@ impl<'a, 'b> Resolver<'a, 'b> {
                 self.resolve_expr(right);
             }
             Expr::Null => {}
+            Expr::Set {
+                object,
+                name: _,
+                value,
+            } => {
+                self.resolve_expr(value);
+                self.resolve_expr(object);
+            }
+            Expr::Super {
+                id,
+                keyword,
+                method: _,
+            } => {
+                if self.current_class.is_none() {
+                    self.lox.parse_error(
+                        keyword.clone(),
+                        "Can't use 'super' outside of a class.",
+                    );
+                } else if !self.current_class.is_subclass() {
+                    self.lox.parse_error(
+                        keyword.clone(),
+                        "Can't use 'super' in a class with no superclass.",
+                    );
+                }
+                self.resolve_local(*id, keyword);
+            }
+            Expr::This { id, keyword } => {
+                if self.current_class.is_none() {
+                    self.lox.parse_error(
+                        keyword.clone(),
+                        "Can't use 'this' outside of a class.",
+                    );
+                    return;
+                }
+                self.resolve_local(*id, keyword);
+            }
             Expr::Unary { operator: _, right } => {
                 self.resolve_expr(right);
             }
-            Expr::Variable { name } => {
+            Expr::Variable { id, name } => {
                 if !self.scopes.is_empty() {
                     let test = self
                         .scopes
                         .peek()
                         .get(&name.lexeme)
-			// if the get fails in Java, null is returned, which is
-			// not equal to false. this also explains the explicit
-			// test against Boolean.FALSE in the java code
+                        // if the get fails in Java, null is returned, which is
+                        // not equal to false. this also explains the explicit
+                        // test against Boolean.FALSE in the java code
                         .unwrap_or(&true);
                     if test == &false {
-                        self.interpreter.lox.parse_error(
+                        self.lox.parse_error(
                             name.clone(),
-                            "Can't read local variable in its own initializer",
+                            "Can't read local variable in its own initializer.",
                         );
                     }
                 }
 
-                self.resolve_local(expr, name);
+                self.resolve_local(*id, name);
             }
         }
     }
@@ -200,8 +368,8 @@ impl<'a, 'b> Resolver<'a, 'b> {
 
         let scope = self.scopes.peek();
         if scope.contains_key(&name.lexeme) {
-            self.interpreter.lox.error(
-                name.line,
+            self.lox.parse_error(
+                name.clone(),
                 "Already a variable with this name in this scope.",
             );
         }
@@ -216,11 +384,10 @@ impl<'a, 'b> Resolver<'a, 'b> {
         self.scopes.peek().insert(name.lexeme.clone(), true);
     }
 
-    fn resolve_local(&mut self, expr: &Expr, name: &Token) {
+    fn resolve_local(&mut self, id: ExprId, name: &Token) {
         for i in (0..self.scopes.len()).rev() {
             if self.scopes[i].contains_key(&name.lexeme) {
-                self.interpreter
-                    .resolve(expr.clone(), self.scopes.len() - 1 - i);
+                self.lox.interpreter.resolve(id, self.scopes.len() - 1 - i);
                 return;
             }
         }
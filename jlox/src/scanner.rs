@@ -9,7 +9,9 @@ use crate::Lox;
 lazy_static! {
     static ref KEYWORDS: HashMap<&'static str, TokenType> = HashMap::from([
         ("and", TokenType::And),
+        ("break", TokenType::Break),
         ("class", TokenType::Class),
+        ("continue", TokenType::Continue),
         ("else", TokenType::Else),
         ("false", TokenType::False),
         ("for", TokenType::For),
@@ -7,6 +7,17 @@ pub(crate) enum Stmt {
     Block {
         statements: Vec<Stmt>,
     },
+    Break {
+        keyword: Token,
+    },
+    Class {
+        name: Token,
+        superclass: Expr,
+        methods: Vec<Stmt>,
+    },
+    Continue {
+        keyword: Token,
+    },
     Expression {
         expression: Expr,
     },
@@ -32,9 +43,12 @@ pub(crate) enum Stmt {
         name: Token,
         initializer: Expr,
     },
+    /// `increment` is only non-null for desugared `for` loops. it runs after
+    /// every iteration of `body`, including ones cut short by `continue`
     While {
         condition: Expr,
         body: Box<Stmt>,
+        increment: Expr,
     },
 }
 
@@ -43,6 +57,18 @@ impl Stmt {
         Self::Block { statements }
     }
 
+    pub(crate) fn class(
+        name: Token,
+        superclass: Expr,
+        methods: Vec<Stmt>,
+    ) -> Self {
+        Self::Class {
+            name,
+            superclass,
+            methods,
+        }
+    }
+
     pub(crate) fn function(
         name: Token,
         params: Vec<Token>,
@@ -90,11 +116,18 @@ impl Display for Stmt {
             Stmt::Var { name, initializer } => {
                 writeln!(f, "(setf {name} {initializer})")
             }
-            Stmt::While { condition, body } => writeln!(
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => writeln!(
                 f,
                 "(while {condition}
-\t{body})"
+\t{body}
+\t{increment})"
             ),
+            Stmt::Break { .. } => writeln!(f, "(break)"),
+            Stmt::Continue { .. } => writeln!(f, "(continue)"),
             Stmt::Function { name, params, body } => {
                 write!(f, "(defun {name} (")?;
                 for param in params {
@@ -113,6 +146,21 @@ impl Display for Stmt {
             Stmt::Return { keyword: _, value } => {
                 writeln!(f, "(return {value})")
             }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                write!(f, "(defclass {}", name.lexeme)?;
+                if !superclass.is_null() {
+                    write!(f, " (:inherit {superclass})")?;
+                }
+                writeln!(f)?;
+                for method in methods {
+                    write!(f, "\t{method}")?;
+                }
+                writeln!(f, ")")
+            }
         }
     }
 }
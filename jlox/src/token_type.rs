@@ -27,7 +27,9 @@ pub(crate) enum TokenType {
     Number,
     // Keywords
     And,
+    Break,
     Class,
+    Continue,
     Else,
     False,
     Fun,